@@ -0,0 +1,169 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hyper::body::Bytes;
+use tokio::{runtime::Runtime, sync::mpsc};
+
+/// Keeps track of how much is allocated, and the most there was at once, so the
+/// memory a backed up channel holds on to can be reported next to its throughput.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// A typical log line, newline included.
+const LINE: &[u8] = b"2024-01-01T00:00:00.000000000Z level=info request served path=/api/v1/items status=200 took=3ms\n";
+const LINES: usize = 64 * 1024;
+
+/// How a consumer keeps up with the reader.
+#[derive(Debug, Clone, Copy)]
+enum Consumer {
+    /// Takes each line as soon as it arrives.
+    Fast,
+    /// Spends a while on every line, like one writing to a terminal or a socket.
+    Slow,
+}
+
+impl Consumer {
+    fn take(self, line: &Bytes) -> u64 {
+        let rounds = match self {
+            Consumer::Fast => 1,
+            Consumer::Slow => 64,
+        };
+        (0..rounds).fold(0, |sum, round| {
+            line.iter().fold(sum, |sum, b| {
+                sum.wrapping_mul(31).wrapping_add(*b as u64 ^ round)
+            })
+        })
+    }
+}
+
+/// Where the reader's lines go: the unbounded channel `ContainerLog` uses now, or
+/// a bounded one of some capacity.
+#[derive(Debug, Clone, Copy)]
+enum Channel {
+    Unbounded,
+    Bounded(usize),
+}
+
+impl std::fmt::Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Channel::Unbounded => write!(f, "unbounded"),
+            Channel::Bounded(capacity) => write!(f, "bounded_{}", capacity),
+        }
+    }
+}
+
+/// Sends `LINES` freshly allocated lines from a reader task, as the decoder hands
+/// them out, to `consumer` on another task.
+async fn stream(channel: Channel, consumer: Consumer) -> u64 {
+    let received = match channel {
+        Channel::Unbounded => {
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                for _ in 0..LINES {
+                    if tx.send(Bytes::from(LINE.to_vec())).is_err() {
+                        return;
+                    }
+                }
+            });
+            tokio::spawn(async move {
+                let mut sum = 0u64;
+                while let Some(line) = rx.recv().await {
+                    sum = sum.wrapping_add(consumer.take(&line));
+                }
+                sum
+            })
+        }
+        Channel::Bounded(capacity) => {
+            let (tx, mut rx) = mpsc::channel(capacity);
+            tokio::spawn(async move {
+                for _ in 0..LINES {
+                    if tx.send(Bytes::from(LINE.to_vec())).await.is_err() {
+                        return;
+                    }
+                }
+            });
+            tokio::spawn(async move {
+                let mut sum = 0u64;
+                while let Some(line) = rx.recv().await {
+                    sum = sum.wrapping_add(consumer.take(&line));
+                }
+                sum
+            })
+        }
+    };
+
+    received.await.unwrap()
+}
+
+/// The reader and the consumer each get a thread, as they would following a busy
+/// container.
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .build()
+        .unwrap()
+}
+
+fn channels(c: &mut Criterion) {
+    let rt = runtime();
+    let channels = [
+        Channel::Unbounded,
+        Channel::Bounded(16),
+        Channel::Bounded(256),
+        Channel::Bounded(4096),
+    ];
+
+    for consumer in [Consumer::Fast, Consumer::Slow] {
+        let mut group = c.benchmark_group(format!("channels_{:?}", consumer).to_lowercase());
+        group.throughput(Throughput::Bytes((LINE.len() * LINES) as u64));
+
+        for channel in channels {
+            // Criterion only reports time, so measure what one run peaks at apart.
+            let before = ALLOCATED.load(Ordering::Relaxed);
+            PEAK.store(before, Ordering::Relaxed);
+            black_box(rt.block_on(stream(channel, consumer)));
+            println!(
+                "{:?} consumer, {}: peak {} KiB over baseline",
+                consumer,
+                channel,
+                PEAK.load(Ordering::Relaxed).saturating_sub(before) / 1024
+            );
+
+            group.bench_with_input(
+                BenchmarkId::from_parameter(channel),
+                &channel,
+                |b, &channel| b.iter(|| black_box(rt.block_on(stream(channel, consumer)))),
+            );
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, channels);
+criterion_main!(benches);
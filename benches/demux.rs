@@ -0,0 +1,53 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use docker_log_stream::FrameDecoder;
+use hyper::body::Bytes;
+
+/// The size hyper typically hands body chunks over in.
+const CHUNK: usize = 16 * 1024;
+const TOTAL: usize = 8 * 1024 * 1024;
+
+/// `TOTAL` bytes of stdout/stderr frames carrying `payload` bytes each, cut into
+/// `CHUNK` sized pieces that ignore frame boundaries like the socket does.
+fn multiplexed(payload: usize) -> Vec<Bytes> {
+    let mut body = Vec::with_capacity(TOTAL + TOTAL / payload * 8);
+    let mut stream = 1;
+
+    while body.len() < TOTAL {
+        body.extend_from_slice(&[stream, 0, 0, 0]);
+        body.extend_from_slice(&(payload as u32).to_be_bytes());
+        body.resize(body.len() + payload, b'x');
+        stream = 3 - stream;
+    }
+
+    body.chunks(CHUNK).map(Bytes::copy_from_slice).collect()
+}
+
+fn demux(c: &mut Criterion) {
+    let mut group = c.benchmark_group("demux");
+
+    for payload in [16, 256, 64 * 1024, 1024 * 1024] {
+        let chunks = multiplexed(payload);
+        let len = chunks.iter().map(Bytes::len).sum::<usize>();
+
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(
+            BenchmarkId::new("frame_payload", payload),
+            &chunks,
+            |b, chunks| {
+                b.iter(|| {
+                    let mut decoder = FrameDecoder::new(false);
+                    let mut frames = 0;
+                    for chunk in chunks {
+                        frames += decoder.push(chunk.clone()).len();
+                    }
+                    black_box(frames)
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, demux);
+criterion_main!(benches);
@@ -0,0 +1,73 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use docker_log_stream::ContainerListDecoder;
+use hyper::body::Bytes;
+
+/// The size hyper typically hands body chunks over in.
+const CHUNK: usize = 16 * 1024;
+const CONTAINERS: usize = 5_000;
+
+/// A `/containers/json` body for `CONTAINERS` containers shaped like what the
+/// daemon returns, cut into `CHUNK` sized pieces.
+fn listing() -> Vec<Bytes> {
+    let entries = (0..CONTAINERS)
+        .map(|i| {
+            format!(
+                r#"{{"Id":"{id:064x}","Names":["/app-{i}"],"Image":"registry.local:5000/app:{i}",
+                "ImageID":"sha256:{id:064x}","Command":"/bin/app --serve","Created":{created},
+                "Ports":[{{"IP":"0.0.0.0","PrivatePort":8080,"PublicPort":{port},"Type":"tcp"}}],
+                "Labels":{{"com.docker.compose.project":"shop","com.docker.compose.service":"app-{i}"}},
+                "State":"running","Status":"Up 3 hours","HostConfig":{{"NetworkMode":"default"}},
+                "NetworkSettings":{{"Networks":{{"bridge":{{"IPAddress":"172.17.0.{octet}"}}}}}},
+                "Mounts":[]}}"#,
+                id = i,
+                i = i,
+                created = 1_700_000_000 + i,
+                port = 10_000 + i,
+                octet = i % 250 + 2,
+            )
+        })
+        .collect::<Vec<_>>();
+    let body = format!("[{}]", entries.join(","));
+
+    body.as_bytes()
+        .chunks(CHUNK)
+        .map(Bytes::copy_from_slice)
+        .collect()
+}
+
+fn decode(c: &mut Criterion) {
+    let chunks = listing();
+    let len = chunks.iter().map(Bytes::len).sum::<usize>();
+
+    let mut group = c.benchmark_group("listing");
+    group.throughput(Throughput::Bytes(len as u64));
+
+    // What listing did before: the whole body first, then one big tree of values.
+    group.bench_function("buffered", |b| {
+        b.iter(|| {
+            let mut body = Vec::with_capacity(len);
+            for chunk in &chunks {
+                body.extend_from_slice(chunk);
+            }
+            let parsed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            black_box(parsed.as_array().map(Vec::len))
+        })
+    });
+
+    group.bench_function("streaming", |b| {
+        b.iter(|| {
+            let mut decoder = ContainerListDecoder::new();
+            let mut containers = 0;
+            for chunk in &chunks {
+                containers += decoder.push(chunk).unwrap().len();
+            }
+            decoder.finish().unwrap();
+            black_box(containers)
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -0,0 +1,81 @@
+/// Removes ANSI escape sequences (colors, cursor movement, window titles) from a
+/// byte stream. The state carries over between calls, so a sequence split across
+/// chunks is still removed whole.
+#[derive(Debug, Default)]
+pub(crate) struct AnsiStripper {
+    state: State,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum State {
+    #[default]
+    Text,
+    /// Just read `ESC`.
+    Escape,
+    /// Inside `ESC [`, until a final byte.
+    Csi,
+    /// Inside `ESC ]`, until `BEL` or `ESC \`.
+    Osc,
+    /// Read `ESC` inside an OSC, which is most likely the start of `ESC \`.
+    OscEscape,
+    /// Inside some other `ESC` sequence, until a final byte.
+    Other,
+}
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+impl AnsiStripper {
+    pub(crate) fn strip(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+
+        for &byte in data {
+            self.state = match (self.state, byte) {
+                (State::Text, ESC) => State::Escape,
+                (State::Text, _) => {
+                    out.push(byte);
+                    State::Text
+                }
+                (State::Escape, b'[') => State::Csi,
+                (State::Escape, b']') => State::Osc,
+                (State::Escape, 0x20..=0x2f) => State::Other,
+                (State::Escape, _) => State::Text,
+                (State::Csi, 0x40..=0x7e) => State::Text,
+                (State::Csi, _) => State::Csi,
+                (State::Osc, BEL) => State::Text,
+                (State::Osc, ESC) => State::OscEscape,
+                (State::Osc, _) => State::Osc,
+                (State::OscEscape, b'\\') => State::Text,
+                (State::OscEscape, _) => State::Osc,
+                (State::Other, 0x30..=0x7e) => State::Text,
+                (State::Other, _) => State::Other,
+            };
+
+            // Escape sequences never span lines; a newline means the sequence was
+            // cut short, and the line it ends still belongs in the output.
+            if byte == b'\n' && self.state != State::Text {
+                out.push(byte);
+                self.state = State::Text;
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnsiStripper;
+
+    #[test]
+    fn strips_sequences_split_across_chunks() {
+        let input = b"\x1b[1;31merror\x1b[0m: \x1b]0;title\x07disk \x1b(Bfull\n";
+
+        for split in 0..input.len() {
+            let mut stripper = AnsiStripper::default();
+            let mut out = stripper.strip(&input[..split]);
+            out.extend(stripper.strip(&input[split..]));
+            assert_eq!(out, b"error: disk full\n", "split at {}", split);
+        }
+    }
+}
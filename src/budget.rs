@@ -0,0 +1,52 @@
+use tokio::sync::Semaphore;
+
+/// Weighs an item against a [`Budget`]. The same function must be used where the
+/// item is admitted and where it is handed to the consumer.
+pub(crate) type Weigh<T> = fn(&T) -> usize;
+
+/// Bytes that may sit in channels between a group of readers and their consumer.
+/// Readers [`take`](Budget::take) before forwarding and wait while the budget is
+/// spent; the consumer [`give`](Budget::give)s back what it has received.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    permits: Semaphore,
+    max: usize,
+}
+
+impl Budget {
+    pub(crate) fn new(max: usize) -> Self {
+        let max = max.clamp(1, u32::MAX as usize);
+
+        Self {
+            permits: Semaphore::new(max),
+            max,
+        }
+    }
+
+    /// Anything larger than the whole budget is charged as the whole budget, so it
+    /// still gets through once everything before it has been consumed.
+    fn charge(&self, bytes: usize) -> u32 {
+        bytes.min(self.max) as u32
+    }
+
+    /// Waits until `bytes` fit. False once the budget is closed, meaning the
+    /// consumer is gone.
+    pub(crate) async fn take(&self, bytes: usize) -> bool {
+        match self.permits.acquire_many(self.charge(bytes)).await {
+            Ok(permit) => {
+                permit.forget();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub(crate) fn give(&self, bytes: usize) {
+        self.permits.add_permits(self.charge(bytes) as usize);
+    }
+
+    /// Stops every reader waiting on the budget.
+    pub(crate) fn close(&self) {
+        self.permits.close();
+    }
+}
@@ -0,0 +1,100 @@
+use serde_json::Value;
+
+use crate::{DockerError, DockerSystem};
+
+/// What happened to a path, as `docker diff` shows it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    Modified,
+    Added,
+    Deleted,
+}
+
+/// A path in a container's filesystem that differs from its image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsChange {
+    pub path: String,
+    pub kind: ChangeKind,
+}
+
+impl FsChange {
+    fn from_json(value: &Value) -> Result<Self, DockerError> {
+        let invalid = || DockerError::InvalidResponse(format!("unexpected change: {}", value));
+
+        let path = value
+            .get("Path")
+            .and_then(Value::as_str)
+            .ok_or_else(invalid)?;
+        let kind = match value.get("Kind").and_then(Value::as_u64) {
+            Some(0) => ChangeKind::Modified,
+            Some(1) => ChangeKind::Added,
+            Some(2) => ChangeKind::Deleted,
+            _ => return Err(invalid()),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            kind,
+        })
+    }
+}
+
+impl DockerSystem {
+    /// The paths container `id` changed since it was created, to go with what its
+    /// logs say it did.
+    pub async fn changes(&self, id: &str) -> Result<Vec<FsChange>, DockerError> {
+        let value = self
+            .transport
+            .get_json(&format!("/containers/{}/changes", id))
+            .await?;
+
+        match value {
+            // An unchanged container comes back as `null` rather than `[]`.
+            Value::Null => Ok(Vec::new()),
+            Value::Array(changes) => changes.iter().map(FsChange::from_json).collect(),
+            other => Err(DockerError::InvalidResponse(format!(
+                "expected a list of changes, got {}",
+                other
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mock, ChangeKind, DockerError, DockerSystem, FsChange};
+
+    #[tokio::test]
+    async fn changes_are_read_by_kind() {
+        let daemon = mock::MockDaemon::start(|req| match req.uri().path() {
+            "/containers/abc/changes" => mock::json(
+                200,
+                r#"[{"Path":"/etc","Kind":0},{"Path":"/etc/app.conf","Kind":1},
+                    {"Path":"/tmp/lock","Kind":2}]"#,
+            ),
+            "/containers/clean/changes" => mock::json(200, "null"),
+            "/containers/odd/changes" => mock::json(200, r#"[{"Path":"/x","Kind":7}]"#),
+            _ => mock::json(404, r#"{"message":"No such container"}"#),
+        });
+        let system = DockerSystem::unrefreshed(daemon.transport());
+
+        let change = |path: &str, kind| FsChange {
+            path: path.into(),
+            kind,
+        };
+        assert_eq!(
+            system.changes("abc").await.unwrap(),
+            vec![
+                change("/etc", ChangeKind::Modified),
+                change("/etc/app.conf", ChangeKind::Added),
+                change("/tmp/lock", ChangeKind::Deleted),
+            ]
+        );
+        assert_eq!(system.changes("clean").await.unwrap(), vec![]);
+        assert!(matches!(
+            system.changes("odd").await,
+            Err(DockerError::InvalidResponse(_))
+        ));
+        assert!(system.changes("gone").await.is_err());
+    }
+}
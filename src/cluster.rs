@@ -0,0 +1,151 @@
+use std::pin::Pin;
+
+use futures_lite::{Stream, StreamExt};
+use futures_util::stream::select_all;
+
+use crate::{ContainerInfo, DockerError, DockerSystem, LogLine, TaskStream};
+
+/// Something that came from one host of a [`DockerCluster`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tagged<T> {
+    /// The name the host was added to the cluster under.
+    pub host: String,
+    pub value: T,
+}
+
+/// Several daemons viewed as one. Each host keeps its own connection; the cluster
+/// only tags and merges what they report.
+#[derive(Debug)]
+pub struct DockerCluster {
+    hosts: Vec<(String, DockerSystem)>,
+}
+
+impl DockerCluster {
+    /// Builds a cluster from `(name, system)` pairs, the name being what results
+    /// from that system are tagged with.
+    pub fn new(hosts: impl IntoIterator<Item = (String, DockerSystem)>) -> Self {
+        Self {
+            hosts: hosts.into_iter().collect(),
+        }
+    }
+
+    /// The running containers of every host, host by host in the order they were
+    /// added. Fails if any host can't be listed.
+    pub async fn containers(&self) -> Result<Vec<Tagged<ContainerInfo>>, DockerError> {
+        let mut all = Vec::new();
+
+        for (host, system) in &self.hosts {
+            all.extend(system.containers().await?.into_iter().map(|info| Tagged {
+                host: host.clone(),
+                value: info,
+            }));
+        }
+
+        Ok(all)
+    }
+
+    /// [`DockerSystem::attach_all`] on every host, merged into one stream. Lines
+    /// from different hosts are interleaved fairly as they arrive.
+    pub async fn attach_all(&self) -> Result<TaskStream<Tagged<LogLine>>, DockerError> {
+        let mut hosts = Vec::new();
+        for (host, system) in &self.hosts {
+            hosts.push((host.clone(), system.attach_all().await?));
+        }
+        let mut merged = merge(hosts);
+
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let handle = tokio::spawn(async move {
+            while let Some(line) = merged.next().await {
+                if tx.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(TaskStream::new(rx, handle))
+    }
+}
+
+/// Tags each host's stream and takes from all of them in turn, so a busy host
+/// can't crowd the others out however many there are.
+fn merge<S, T>(hosts: Vec<(String, S)>) -> Pin<Box<dyn Stream<Item = Tagged<T>> + Send>>
+where
+    S: Stream<Item = T> + Send + Unpin + 'static,
+    T: Send + 'static,
+{
+    let tagged = hosts.into_iter().map(|(host, values)| {
+        values.map(move |value| Tagged {
+            host: host.clone(),
+            value,
+        })
+    });
+
+    Box::pin(select_all(tagged))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::{stream, StreamExt};
+
+    use super::merge;
+    use crate::{
+        supervisor::tests::{daemon, greeting},
+        DockerCluster, DockerSystem,
+    };
+
+    #[tokio::test]
+    async fn containers_and_lines_are_tagged_by_host() {
+        let (a, b) = ("a".repeat(64), "b".repeat(64));
+        let (east, _east_events) = daemon(format!(r#"[{{"Id":"{}"}}]"#, a), greeting);
+        let (west, _west_events) = daemon(format!(r#"[{{"Id":"{}"}}]"#, b), greeting);
+
+        let cluster = DockerCluster::new(vec![
+            (
+                "east".to_owned(),
+                DockerSystem::unrefreshed(east.transport()),
+            ),
+            (
+                "west".to_owned(),
+                DockerSystem::unrefreshed(west.transport()),
+            ),
+        ]);
+
+        let containers = cluster
+            .containers()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.host, c.value.id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            containers,
+            vec![
+                ("east".to_owned(), a.clone()),
+                ("west".to_owned(), b.clone())
+            ]
+        );
+
+        let mut lines = cluster.attach_all().await.unwrap();
+        let mut seen = Vec::new();
+        for _ in 0..2 {
+            let line = lines.next().await.unwrap();
+            seen.push((line.host, line.value.container_id));
+        }
+        seen.sort();
+        assert_eq!(seen, vec![("east".to_owned(), a), ("west".to_owned(), b)]);
+    }
+
+    #[tokio::test]
+    async fn busy_hosts_take_turns_with_the_rest() {
+        let hosts = ["a", "b", "c"]
+            .iter()
+            .map(|host| (host.to_string(), stream::repeat(*host).take(100)))
+            .collect();
+
+        let first = merge(hosts).take(30).collect::<Vec<_>>().await;
+        for host in ["a", "b", "c"] {
+            let taken = first.iter().filter(|line| line.host == host).count();
+            assert_eq!(taken, 10, "{:?}", first);
+        }
+    }
+}
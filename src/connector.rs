@@ -0,0 +1,217 @@
+//! Connects to the daemon's unix socket like [`hyperlocal::UnixConnector`] does,
+//! or to its TCP port, tuning each connection's socket options on the way.
+
+use std::{
+    future::Future,
+    io,
+    os::unix::io::{AsRawFd, RawFd},
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use hyper::{
+    client::connect::{Connected, Connection},
+    service::Service,
+    Uri,
+};
+use nix::sys::socket::{setsockopt, sockopt};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+use tokio_rustls::{client::TlsStream, rustls::ClientConfig, TlsConnector};
+
+use crate::tls;
+
+/// Opens connections for the addresses [`hyperlocal::Uri`] builds, whose host is
+/// the hex encoded socket path, and for `http://host:port` ones over TCP.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Connector {
+    /// See [`DockerConfig::recv_buffer_bytes`](crate::DockerConfig::recv_buffer_bytes).
+    pub(crate) recv_buffer_bytes: Option<usize>,
+    /// Speak TLS on TCP connections, see [`TcpEndpoint::tls`](crate::TcpEndpoint::tls).
+    pub(crate) tls: Option<Arc<ClientConfig>>,
+}
+
+impl Connector {
+    async fn connect(self, uri: Uri) -> io::Result<DaemonStream> {
+        if uri.scheme_str() == Some("unix") {
+            let stream = tokio::net::UnixStream::connect(socket_path(&uri)?).await?;
+            self.tune(stream.as_raw_fd());
+            return Ok(DaemonStream::Unix(stream));
+        }
+
+        let (host, port) = match (uri.scheme_str(), uri.host(), uri.port_u16()) {
+            (Some("http"), Some(host), Some(port)) => {
+                (host.trim_start_matches('[').trim_end_matches(']'), port)
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("not a daemon uri: {}", uri),
+                ))
+            }
+        };
+        let stream = TcpStream::connect((host, port)).await?;
+        self.tune(stream.as_raw_fd());
+        let _ = stream.set_nodelay(true);
+
+        match self.tls {
+            Some(config) => {
+                let name = tls::server_name(host).map_err(io::Error::other)?;
+                let stream = TlsConnector::from(config).connect(name, stream).await?;
+                Ok(DaemonStream::Tls(Box::new(stream)))
+            }
+            None => Ok(DaemonStream::Tcp(stream)),
+        }
+    }
+
+    fn tune(&self, fd: RawFd) {
+        if let Some(bytes) = self.recv_buffer_bytes {
+            // Only a tuning: a socket the option can't be set on still works.
+            if let Err(e) = setsockopt(fd, sockopt::RcvBuf, &bytes) {
+                tracing::debug!(bytes, error = %e, "could not set SO_RCVBUF");
+            }
+        }
+    }
+}
+
+impl Service<Uri> for Connector {
+    type Response = DaemonStream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<DaemonStream>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        Box::pin(self.clone().connect(uri))
+    }
+}
+
+fn socket_path(uri: &Uri) -> io::Result<PathBuf> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("not a unix uri: {}", uri),
+        )
+    };
+    if uri.scheme_str() != Some("unix") {
+        return Err(invalid());
+    }
+
+    let path = uri
+        .host()
+        .and_then(|host| hex::decode(host).ok())
+        .ok_or_else(invalid)?;
+    Ok(PathBuf::from(String::from_utf8_lossy(&path).into_owned()))
+}
+
+/// A connection to the daemon.
+#[derive(Debug)]
+pub(crate) enum DaemonStream {
+    Unix(tokio::net::UnixStream),
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl DaemonStream {
+    #[cfg(test)]
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            DaemonStream::Unix(stream) => stream.as_raw_fd(),
+            DaemonStream::Tcp(stream) => stream.as_raw_fd(),
+            DaemonStream::Tls(stream) => stream.get_ref().0.as_raw_fd(),
+        }
+    }
+}
+
+impl AsyncRead for DaemonStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            DaemonStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            DaemonStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            DaemonStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for DaemonStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            DaemonStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            DaemonStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            DaemonStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            DaemonStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            DaemonStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            DaemonStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            DaemonStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+            DaemonStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            DaemonStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+impl Connection for DaemonStream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{service::Service, Response};
+    use nix::sys::socket::{getsockopt, sockopt};
+
+    use super::Connector;
+    use crate::mock;
+
+    #[tokio::test]
+    async fn receive_buffer_is_applied_per_connection() {
+        let daemon = mock::MockDaemon::start(|_| Response::new(Default::default()));
+        let uri: hyper::Uri = hyperlocal::Uri::new(&daemon.socket, "/").into();
+        let recv_buffer = |mut connector: Connector| {
+            let uri = uri.clone();
+            async move {
+                let stream = connector.call(uri).await.unwrap();
+                getsockopt(stream.as_raw_fd(), sockopt::RcvBuf).unwrap()
+            }
+        };
+
+        let untuned = recv_buffer(Connector::default()).await;
+        let tuned = recv_buffer(Connector {
+            recv_buffer_bytes: Some(4096),
+            ..Default::default()
+        })
+        .await;
+        // The kernel is free to round what it was asked for, but not to ignore it.
+        assert_ne!(tuned, untuned);
+        assert!(tuned >= 4096, "{}", tuned);
+
+        assert!(Connector::default()
+            .call("http://localhost/".parse().unwrap())
+            .await
+            .is_err());
+    }
+}
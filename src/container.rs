@@ -0,0 +1,562 @@
+use std::{collections::HashMap, convert::TryFrom};
+
+use hyper::{body::HttpBody, StatusCode};
+use serde_json::Value;
+
+use crate::{
+    container_log,
+    json::ArraySplitter,
+    line::LogLine,
+    transport::{filters_query, Transport},
+    DockerError, DockerSystem,
+};
+
+/// A container as summarized by `/containers/json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerInfo {
+    pub id: String,
+    pub names: Vec<String>,
+    pub image: String,
+    /// The state as the daemon wrote it, see [`ContainerInfo::container_state`].
+    pub state: String,
+    pub status: String,
+    /// Unix timestamp of when the container was created.
+    pub created: i64,
+    pub labels: HashMap<String, String>,
+    pub ports: Vec<PortMapping>,
+    /// Names of the networks the container is connected to.
+    pub networks: Vec<String>,
+}
+
+/// Where a container is in its lifecycle, as `/containers/json` reports it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ContainerState {
+    Created,
+    Running,
+    Paused,
+    Restarting,
+    Removing,
+    Exited,
+    Dead,
+    /// A state this crate doesn't know about yet, as the daemon wrote it.
+    Unknown(String),
+}
+
+impl ContainerState {
+    pub fn parse(state: &str) -> Self {
+        match state {
+            "created" => ContainerState::Created,
+            "running" => ContainerState::Running,
+            "paused" => ContainerState::Paused,
+            "restarting" => ContainerState::Restarting,
+            "removing" => ContainerState::Removing,
+            "exited" => ContainerState::Exited,
+            "dead" => ContainerState::Dead,
+            other => ContainerState::Unknown(other.to_owned()),
+        }
+    }
+
+    /// The name the daemon uses, e.g. for a `status` filter.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContainerState::Created => "created",
+            ContainerState::Running => "running",
+            ContainerState::Paused => "paused",
+            ContainerState::Restarting => "restarting",
+            ContainerState::Removing => "removing",
+            ContainerState::Exited => "exited",
+            ContainerState::Dead => "dead",
+            ContainerState::Unknown(state) => state,
+        }
+    }
+}
+
+/// A container port and, if it is published, the host port it is reachable on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortMapping {
+    pub private: u16,
+    pub public: Option<u16>,
+    pub type_: String,
+}
+
+/// What changed between two refreshes of a [`DockerSystem`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContainerDelta {
+    /// Containers that are running now but weren't at the previous refresh, as
+    /// that refresh's listing described them.
+    pub started: Vec<ContainerInfo>,
+    /// Containers that were running at the previous refresh but aren't any more,
+    /// as that previous refresh described them.
+    pub stopped: Vec<ContainerInfo>,
+}
+
+impl ContainerInfo {
+    /// [`ContainerInfo::state`], for matching on without comparing strings.
+    pub fn container_state(&self) -> ContainerState {
+        ContainerState::parse(&self.state)
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Result<Self, DockerError> {
+        let id = value
+            .get("Id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| DockerError::InvalidResponse("container without an Id".into()))?
+            .to_owned();
+
+        let string = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned()
+        };
+
+        let names = value
+            .get("Names")
+            .and_then(Value::as_array)
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let labels = value
+            .get("Labels")
+            .and_then(Value::as_object)
+            .map(|labels| {
+                labels
+                    .iter()
+                    .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_owned())))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let ports = value
+            .get("Ports")
+            .and_then(Value::as_array)
+            .map(|ports| ports.iter().filter_map(PortMapping::from_json).collect())
+            .unwrap_or_default();
+
+        let networks = value
+            .pointer("/NetworkSettings/Networks")
+            .and_then(Value::as_object)
+            .map(|networks| networks.keys().cloned().collect())
+            .unwrap_or_default();
+
+        Ok(Self {
+            id,
+            names,
+            image: string("Image"),
+            state: string("State"),
+            status: string("Status"),
+            created: value
+                .get("Created")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+            labels,
+            ports,
+            networks,
+        })
+    }
+}
+
+impl PortMapping {
+    fn from_json(value: &Value) -> Option<Self> {
+        // Out of range is as good as missing, rather than wrapped round to some
+        // other port.
+        let port = |key: &str| u16::try_from(value.get(key)?.as_u64()?).ok();
+
+        Some(Self {
+            private: port("PrivatePort")?,
+            public: port("PublicPort"),
+            type_: value.get("Type")?.as_str()?.to_owned(),
+        })
+    }
+}
+
+/// Parses a `/containers/json` body as it arrives, a summary at a time, so a host
+/// with thousands of containers never has its whole listing in memory at once.
+#[derive(Debug, Default)]
+pub struct ContainerListDecoder {
+    splitter: ArraySplitter,
+}
+
+impl ContainerListDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of the body and returns the containers it completed.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<ContainerInfo>, DockerError> {
+        self.splitter
+            .push(chunk)?
+            .iter()
+            .map(|element| ContainerInfo::from_json(&serde_json::from_slice(element)?))
+            .collect()
+    }
+
+    /// Checks the body held a complete listing once it has ended.
+    pub fn finish(self) -> Result<(), DockerError> {
+        self.splitter.finish()
+    }
+}
+
+/// Splits `image` into its repository and its tag or digest, if it has one. A
+/// `:` before the last `/` belongs to a registry port, not to a tag.
+fn split_tag(image: &str) -> (&str, Option<&str>) {
+    if let Some(at) = image.find('@') {
+        return (&image[..at], Some(&image[at + 1..]));
+    }
+
+    match image.rfind(':') {
+        Some(colon) if !image[colon..].contains('/') => {
+            (&image[..colon], Some(&image[colon + 1..]))
+        }
+        _ => (image, None),
+    }
+}
+
+fn image_matches(image: &str, wanted: &str) -> bool {
+    let (repository, tag) = split_tag(image);
+
+    match split_tag(wanted) {
+        (wanted_repository, None) => repository == wanted_repository,
+        (wanted_repository, Some(wanted_tag)) => {
+            repository == wanted_repository && tag.unwrap_or("latest") == wanted_tag
+        }
+    }
+}
+
+/// Lists containers; `query` is passed through to `/containers/json`.
+pub(crate) async fn list(
+    transport: &Transport,
+    query: &str,
+) -> Result<Vec<ContainerInfo>, DockerError> {
+    let mut response = transport
+        .get_ok(&format!("/containers/json{}", query))
+        .await?;
+
+    let mut decoder = ContainerListDecoder::new();
+    let mut containers = Vec::new();
+    while let Some(chunk) = response.data().await {
+        containers.extend(decoder.push(&chunk?)?);
+    }
+    decoder.finish()?;
+
+    Ok(containers)
+}
+
+impl DockerSystem {
+    /// Lists the running containers with the details the daemon summarizes them with.
+    pub async fn containers(&self) -> Result<Vec<ContainerInfo>, DockerError> {
+        list(&self.transport, "").await
+    }
+
+    /// [`DockerSystem::containers`], most recently created first.
+    pub async fn containers_by_created(&self) -> Result<Vec<ContainerInfo>, DockerError> {
+        let mut containers = self.containers().await?;
+        containers.sort_by_key(|c| std::cmp::Reverse(c.created));
+        Ok(containers)
+    }
+
+    /// The running containers started from `image`. Without a tag (`nginx`) any
+    /// tag of the image matches; with one (`nginx:1.25`) only that tag does.
+    pub async fn containers_by_image(
+        &self,
+        image: &str,
+    ) -> Result<Vec<ContainerInfo>, DockerError> {
+        let mut containers = self.containers().await?;
+        containers.retain(|c| image_matches(&c.image, image));
+        Ok(containers)
+    }
+
+    /// The running containers connected to the network `network`, given by name
+    /// or id as `docker network ls` shows them.
+    pub async fn containers_in_network(
+        &self,
+        network: &str,
+    ) -> Result<Vec<ContainerInfo>, DockerError> {
+        let filters = serde_json::json!({ "network": [network] });
+        list(&self.transport, &filters_query(&filters)).await
+    }
+
+    /// The last `n` lines of every stopped container, by container id, for seeing
+    /// why things died after a crash loop. Containers removed before their logs
+    /// could be read, and those whose log driver can't be read back, are left out.
+    pub async fn last_logs_of_stopped(
+        &self,
+        n: usize,
+    ) -> Result<HashMap<String, Vec<LogLine>>, DockerError> {
+        let stopped = serde_json::json!({ "status": ["exited", "dead"] });
+        let query = format!("{}&all=1", filters_query(&stopped));
+
+        let mut logs = HashMap::new();
+        for ContainerInfo { id, .. } in list(&self.transport, &query).await? {
+            match container_log::last_lines(&self.transport, &id, n).await {
+                Ok(lines) => {
+                    logs.insert(id, lines);
+                }
+                Err(DockerError::Http { status, .. }) if status == StatusCode::NOT_FOUND => {}
+                Err(DockerError::LogDriverUnsupported { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(logs)
+    }
+
+    /// Checks a single container without listing everything. A container that has
+    /// been removed is reported as not running rather than as an error.
+    pub async fn is_running(&self, id: &str) -> Result<bool, DockerError> {
+        let inspect = match self
+            .transport
+            .get_json(&format!("/containers/{}/json", id))
+            .await
+        {
+            Ok(inspect) => inspect,
+            Err(DockerError::Http { status, .. }) if status == StatusCode::NOT_FOUND => {
+                return Ok(false)
+            }
+            Err(e) => return Err(e),
+        };
+
+        inspect
+            .pointer("/State/Running")
+            .and_then(Value::as_bool)
+            .ok_or_else(|| DockerError::InvalidResponse("inspect has no State.Running".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{image_matches, ContainerInfo, ContainerListDecoder, ContainerState, PortMapping};
+    use hyper::Response;
+
+    use crate::{mock, transport::query_escape, DockerError, DockerSystem, StreamKind};
+
+    #[tokio::test]
+    async fn containers_sorted_newest_first() {
+        let daemon = mock::MockDaemon::start(|_| {
+            mock::json(
+                200,
+                r#"[{"Id":"old","Created":1600000000},{"Id":"new","Created":1700000000},{"Id":"mid","Created":1650000000}]"#,
+            )
+        });
+        let system = DockerSystem::unrefreshed(daemon.transport());
+
+        let ids = system
+            .containers_by_created()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["new", "mid", "old"]);
+    }
+
+    #[tokio::test]
+    async fn containers_filtered_by_image() {
+        let daemon = mock::MockDaemon::start(|_| {
+            mock::json(
+                200,
+                r#"[{"Id":"a","Image":"nginx:1.25"},{"Id":"b","Image":"nginx"},{"Id":"c","Image":"nginx-exporter:1"},{"Id":"d","Image":"redis:7"}]"#,
+            )
+        });
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let ids = |containers: Vec<ContainerInfo>| {
+            containers.into_iter().map(|c| c.id).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ids(system.containers_by_image("nginx").await.unwrap()),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            ids(system.containers_by_image("nginx:1.25").await.unwrap()),
+            vec!["a"]
+        );
+        assert_eq!(
+            ids(system.containers_by_image("nginx:latest").await.unwrap()),
+            vec!["b"]
+        );
+    }
+
+    #[tokio::test]
+    async fn containers_filtered_by_network() {
+        let daemon = mock::MockDaemon::start(|req| {
+            let query = req.uri().query().unwrap_or_default();
+            if query.contains(&query_escape(r#""network":["mesh"]"#)) {
+                mock::json(200, r#"[{"Id":"a"}]"#)
+            } else {
+                mock::json(200, r#"[{"Id":"a"},{"Id":"b"}]"#)
+            }
+        });
+        let system = DockerSystem::unrefreshed(daemon.transport());
+
+        let containers = system.containers_in_network("mesh").await.unwrap();
+        assert_eq!(
+            containers.into_iter().map(|c| c.id).collect::<Vec<_>>(),
+            vec!["a"]
+        );
+    }
+
+    #[test]
+    fn listing_decodes_a_container_at_a_time() {
+        let body = br#"[{"Id":"a","Names":["/web"]},{"Id":"b","Labels":{"k":"v]"}}]"#;
+        let mut decoder = ContainerListDecoder::new();
+
+        let (first, rest) = body.split_at(30);
+        let ids = |containers: Vec<ContainerInfo>| {
+            containers.into_iter().map(|c| c.id).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(decoder.push(first).unwrap()), vec!["a"]);
+        assert_eq!(ids(decoder.push(rest).unwrap()), vec!["b"]);
+        decoder.finish().unwrap();
+
+        let mut invalid = ContainerListDecoder::new();
+        assert!(matches!(
+            invalid.push(br#"[{"Names":[]}]"#),
+            Err(DockerError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn last_logs_of_stopped_containers() {
+        let daemon = mock::MockDaemon::start(|req| {
+            let path = req.uri().path();
+            let query = req.uri().query().unwrap_or_default();
+            match path {
+                "/containers/json" => {
+                    assert!(query.contains("all=1"), "{}", query);
+                    mock::json(200, r#"[{"Id":"crashed"},{"Id":"tty"},{"Id":"removed"}]"#)
+                }
+                "/containers/crashed/json" => mock::json(200, r#"{"Config":{"Tty":false}}"#),
+                "/containers/tty/json" => mock::json(200, r#"{"Config":{"Tty":true}}"#),
+                "/containers/crashed/logs" => {
+                    assert_eq!(query, "stdout=1&stderr=1&tail=2");
+                    let mut body = mock::frame(1, b"starting\n");
+                    body.extend(mock::frame(2, b"panic: out of memory\n"));
+                    Response::new(body.into())
+                }
+                "/containers/tty/logs" => Response::new("bye\n".into()),
+                _ => mock::json(404, r#"{"message":"No such container"}"#),
+            }
+        });
+        let system = DockerSystem::unrefreshed(daemon.transport());
+
+        let logs = system.last_logs_of_stopped(2).await.unwrap();
+        let messages = |id: &str| {
+            logs[id]
+                .iter()
+                .map(|l| (l.stream, l.message.as_str()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            messages("crashed"),
+            vec![
+                (StreamKind::Stdout, "starting"),
+                (StreamKind::Stderr, "panic: out of memory")
+            ]
+        );
+        assert_eq!(messages("tty"), vec![(StreamKind::Stdout, "bye")]);
+        assert!(!logs.contains_key("removed"));
+
+        let status = query_escape(r#"{"status":["exited","dead"]}"#);
+        assert!(daemon.requests()[0].contains(&status));
+    }
+
+    #[test]
+    fn registry_ports_are_not_tags() {
+        assert!(image_matches("localhost:5000/app:2", "localhost:5000/app"));
+        assert!(image_matches("localhost:5000/app", "localhost:5000/app"));
+        assert!(!image_matches("localhost:5000/app", "localhost"));
+        assert!(image_matches("app@sha256:abcd", "app"));
+    }
+
+    #[tokio::test]
+    async fn is_running_treats_removed_as_stopped() {
+        let daemon = mock::MockDaemon::start(|req| match req.uri().path() {
+            "/containers/up/json" => mock::json(200, r#"{"State":{"Running":true}}"#),
+            "/containers/exited/json" => mock::json(200, r#"{"State":{"Running":false}}"#),
+            "/containers/broken/json" => mock::json(500, r#"{"message":"oops"}"#),
+            _ => mock::json(404, r#"{"message":"No such container"}"#),
+        });
+        let system = DockerSystem::unrefreshed(daemon.transport());
+
+        assert!(system.is_running("up").await.unwrap());
+        assert!(!system.is_running("exited").await.unwrap());
+        assert!(!system.is_running("gone").await.unwrap());
+        assert!(matches!(
+            system.is_running("broken").await,
+            Err(DockerError::Http { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_summary_with_ports() {
+        let summary = serde_json::json!({
+            "Id": "8dfafdbc3a40",
+            "Names": ["/web"],
+            "Image": "nginx:1.25",
+            "State": "running",
+            "Status": "Up 2 minutes",
+            "Labels": {"com.docker.compose.project": "shop"},
+            "Ports": [
+                {"IP": "0.0.0.0", "PrivatePort": 80, "PublicPort": 8080, "Type": "tcp"},
+                {"PrivatePort": 443, "Type": "tcp"},
+                {"PrivatePort": 53, "PublicPort": 65589, "Type": "udp"},
+                {"PrivatePort": 65616, "PublicPort": 8081, "Type": "tcp"}
+            ],
+            "NetworkSettings": {"Networks": {"shop_default": {"NetworkID": "f2de39df4171"}}}
+        });
+
+        let info = ContainerInfo::from_json(&summary).unwrap();
+
+        assert_eq!(info.names, vec!["/web"]);
+        assert_eq!(info.container_state(), ContainerState::Running);
+        assert_eq!(info.labels["com.docker.compose.project"], "shop");
+        assert_eq!(info.networks, vec!["shop_default"]);
+        assert_eq!(
+            info.ports,
+            vec![
+                PortMapping {
+                    private: 80,
+                    public: Some(8080),
+                    type_: "tcp".into()
+                },
+                PortMapping {
+                    private: 443,
+                    public: None,
+                    type_: "tcp".into()
+                },
+                PortMapping {
+                    private: 53,
+                    public: None,
+                    type_: "udp".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn states_are_typed() {
+        for state in [
+            ContainerState::Created,
+            ContainerState::Running,
+            ContainerState::Paused,
+            ContainerState::Restarting,
+            ContainerState::Removing,
+            ContainerState::Exited,
+            ContainerState::Dead,
+        ] {
+            assert_eq!(ContainerState::parse(state.as_str()), state);
+        }
+
+        let future = ContainerState::parse("hibernating");
+        assert_eq!(future, ContainerState::Unknown("hibernating".into()));
+        assert_eq!(future.as_str(), "hibernating");
+    }
+}
@@ -0,0 +1,2585 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::BytesMut;
+use futures_lite::future;
+use hyper::{
+    body::{Bytes, HttpBody},
+    Body, Response, StatusCode,
+};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    task::JoinHandle,
+    try_join,
+};
+
+use crate::{
+    budget::{Budget, Weigh},
+    frame::{Frame, FrameDecoder, StreamKind},
+    level::LogLevel,
+    line::{LineDecoder, LineOptions, LogLine},
+    timestamp,
+    transport::{decoded, http_error, read_body, Transport},
+    DockerError, DockerSystem,
+};
+
+/// Turns body chunks into channel items. Called with `None` once the body ends so
+/// anything still buffered can be flushed.
+type Decoder<T> = Box<dyn FnMut(Option<Bytes>) -> Vec<T> + Send>;
+
+/// Opens a stream's follow again once its connection has dropped, returning the
+/// new body and the decoder to read it with, or `None` when the container has
+/// stopped and there is nothing left to follow.
+type Reconnect<T> = Box<dyn FnMut() -> future::Boxed<Option<(Response<Body>, Decoder<T>)>> + Send>;
+
+/// How long a [resumable](ContainerLogOptions::resume) follow first waits for the
+/// daemon to come back, doubling up to [`MAX_RECONNECT_DELAY`] while it doesn't.
+const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Where a follow starts reading from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Since {
+    At(SystemTime),
+    /// Relative to when the follow is opened.
+    Ago(Duration),
+    /// A string in the forms `docker logs --since` accepts: unix seconds
+    /// (`1704067200`, `1704067200.5`), an RFC 3339 date or time (`2024-01-01`,
+    /// `2024-01-01T00:00:00`, `2024-01-01T00:00:00+01:00`) or a duration ago
+    /// (`10m`, `1h30m`). The daemon itself only takes seconds, so the others are
+    /// converted when the follow is opened, as the CLI does, except that a date or
+    /// time without an offset is taken as UTC rather than local time. Anything
+    /// else fails to open with [`DockerError::InvalidConfig`].
+    Raw(String),
+}
+
+/// Seconds since the epoch, clamped to 0 for a time before it, as a clock set
+/// far enough back could make [`Since::Ago`] or the stderr default.
+fn unix_secs(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// `secs.nanos` since the epoch, the most precise `since` the daemon takes.
+fn epoch_seconds(at: SystemTime) -> String {
+    let since = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:09}", since.as_secs(), since.subsec_nanos())
+}
+
+impl Since {
+    fn query_value(&self) -> String {
+        let at = match self {
+            Since::At(at) => *at,
+            Since::Ago(ago) => SystemTime::now().checked_sub(*ago).unwrap_or(UNIX_EPOCH),
+            // Validated when the follow was opened.
+            Since::Raw(raw) => return raw_seconds(raw, SystemTime::now()).unwrap_or_default(),
+        };
+
+        unix_secs(at).to_string()
+    }
+
+    fn validate(&self) -> Result<(), DockerError> {
+        match self {
+            Since::Raw(raw) if raw_seconds(raw, SystemTime::now()).is_none() => {
+                Err(DockerError::InvalidConfig(format!(
+                    "since {:?} is neither a timestamp nor a duration",
+                    raw
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A [`Since::Raw`] as the seconds the daemon takes, with durations counted back
+/// from `now`. `None` when it is in none of the accepted forms.
+fn raw_seconds(raw: &str, now: SystemTime) -> Option<String> {
+    let seconds = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    match raw.split_once('.') {
+        Some((whole, fraction)) if seconds(whole) && seconds(fraction) => {
+            return Some(raw.to_owned())
+        }
+        None if seconds(raw) => return Some(raw.to_owned()),
+        _ => {}
+    }
+
+    if let Some(ago) = go_duration(raw) {
+        return Some(epoch_seconds(now.checked_sub(ago).unwrap_or(UNIX_EPOCH)));
+    }
+
+    // The CLI's layouts: a date or a date and time, each with or without an
+    // offset. `timestamp::parse` only knows the full form, so fill in the rest.
+    let (date, rest) = (raw.get(..10)?, &raw[10..]);
+    let full = match rest.as_bytes().first() {
+        None => format!("{}T00:00:00Z", date),
+        Some(b'Z' | b'+' | b'-') => format!("{}T00:00:00{}", date, rest),
+        Some(b'T') if rest.ends_with('Z') || rest[1..].contains(['+', '-']) => raw.to_owned(),
+        Some(b'T') => format!("{}Z", raw),
+        Some(_) => return None,
+    };
+    timestamp::parse(&full).map(epoch_seconds)
+}
+
+/// A duration the way Go writes one and `--since` takes it, like `90s`, `1h30m`
+/// or `1.5h`.
+fn go_duration(s: &str) -> Option<Duration> {
+    match s {
+        "" => return None,
+        "0" => return Some(Duration::ZERO),
+        _ => {}
+    }
+
+    let mut rest = s;
+    let mut nanos = 0f64;
+    while !rest.is_empty() {
+        let number_len = rest
+            .bytes()
+            .take_while(|b| b.is_ascii_digit() || *b == b'.')
+            .count();
+        let number = rest[..number_len].parse::<f64>().ok()?;
+        rest = &rest[number_len..];
+
+        let unit_len = rest
+            .char_indices()
+            .find(|(_, c)| c.is_ascii_digit() || *c == '.')
+            .map_or(rest.len(), |(i, _)| i);
+        let unit = match &rest[..unit_len] {
+            "ns" => 1.0,
+            "us" | "µs" => 1e3,
+            "ms" => 1e6,
+            "s" => 1e9,
+            "m" => 60e9,
+            "h" => 3_600e9,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        nanos += number * unit;
+    }
+
+    Some(Duration::from_nanos(nanos as u64))
+}
+
+/// A chunk of a stream's body, numbered in the order it was read, from
+/// [`ContainerLog::sequenced`]. Each stream counts from 0 on its own, without
+/// gaps, so a consumer merging several sources can tell a chunk that went missing
+/// or came out of order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequenced {
+    pub seq: u64,
+    pub data: Bytes,
+}
+
+/// Controls what [`ContainerLog`] asks the daemon for.
+#[derive(Debug, Clone)]
+pub struct ContainerLogOptions {
+    /// Follow the container's stdout. When disabled no request is made for it and
+    /// the `stdout` receiver is closed from the start.
+    pub stdout: bool,
+    /// Follow the container's stderr, see `stdout`.
+    ///
+    /// A TTY container only has one stream. The container is looked up first, and
+    /// if it has a TTY its output is followed with a single request and all of it
+    /// arrives on `stdout`, whichever of the two were asked for.
+    pub stderr: bool,
+    /// Only start from this point. When unset stdout starts from the beginning of
+    /// the container's log and stderr from the moment the follow is opened.
+    pub since: Option<Since>,
+    /// Only replay the last `tail` lines of history before following.
+    pub tail: Option<usize>,
+    /// Stop following after this many lines across stdout and stderr combined,
+    /// closing both channels. Only available in line mode.
+    pub max_lines: Option<usize>,
+    /// Gather whatever the socket already has buffered, up to this many bytes,
+    /// before forwarding it, and never forward more than this at once. In line mode
+    /// longer lines are delivered in pieces of this size. When unset every chunk
+    /// is forwarded the moment it is read, and lines are never split.
+    pub read_buffer_bytes: Option<usize>,
+    /// Deliver a run of identical consecutive lines as the line once, followed by
+    /// a `... repeated K times` line when the run ends. Only available in line mode.
+    pub collapse_repeats: bool,
+    /// Remove ANSI escape sequences, such as colors, from every line. Only
+    /// available in line mode.
+    pub strip_ansi: bool,
+    /// Ask the daemon to prefix every line with the RFC 3339 time it was logged
+    /// at. In line mode the prefix is parsed into [`LogLine::timestamp`] and taken
+    /// off the message, which lets [`ContainerLog::recv_ordered`] put stdout and
+    /// stderr back in the order they were written.
+    ///
+    /// A daemon or log driver that ignores the request sends lines without the
+    /// prefix. Each of those is delivered whole with no timestamp, so output that
+    /// is only partly stamped still comes through intact.
+    pub timestamps: bool,
+    /// With `tail`, close the gap `tail` and `follow` leave between them. The daemon
+    /// takes its tail snapshot and starts following at slightly different moments,
+    /// so a line written in between can be missed or delivered twice. Instead the
+    /// tail is fetched on its own first and the follow picks up exactly after its
+    /// last line, matched by timestamp. Only available in line mode, and exact to
+    /// the daemon's timestamp resolution.
+    pub bridge_tail: bool,
+    /// Only deliver lines that are JSON objects with a `level` (see
+    /// [`LogLevel::parse`]) at least this severe, for "show me only errors". Only
+    /// available in line mode.
+    pub min_level: Option<LogLevel>,
+    /// With `min_level`, drop lines there is no level to read from (plain text,
+    /// JSON without a `level`, pieces of lines split by `read_buffer_bytes`)
+    /// instead of passing them through unchanged.
+    pub drop_unstructured: bool,
+    /// Keep following across a dropped daemon connection, such as a daemon
+    /// restart. While the container is still running the follow is reopened after
+    /// the last line delivered, matched by timestamp as with `bridge_tail`, so
+    /// nothing is missed or repeated; a line the connection was cut off in the
+    /// middle of arrives again whole. With `tail` the tail is bridged as well. Only
+    /// available in line mode.
+    pub resume: bool,
+    /// When neither stream has sent a byte this long after the follow opened, emit
+    /// one `tracing::warn!` and flip [`ContainerLog::waiting_for_output`], to tell a
+    /// container that is up but silent from a follow that failed. The follow itself
+    /// carries on as usual, and [`ContainerLog::wait_for`] running out of time on
+    /// such a container fails with [`DockerError::NoOutput`].
+    pub startup_grace: Option<Duration>,
+}
+
+impl Default for ContainerLogOptions {
+    fn default() -> Self {
+        Self {
+            stdout: true,
+            stderr: true,
+            since: None,
+            tail: None,
+            max_lines: None,
+            read_buffer_bytes: None,
+            collapse_repeats: false,
+            strip_ansi: false,
+            timestamps: false,
+            bridge_tail: false,
+            min_level: None,
+            drop_unstructured: false,
+            resume: false,
+            startup_grace: None,
+        }
+    }
+}
+
+impl ContainerLogOptions {
+    /// Checks what can be checked before anything is asked of the daemon.
+    fn validate(&self) -> Result<(), DockerError> {
+        match &self.since {
+            Some(since) => since.validate(),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether `stream` gets a reader of its own. A TTY container has just the one
+    /// stream, which the stdout reader asks for whichever of the two were wanted.
+    fn reads(&self, stream: StreamKind, tty: bool) -> bool {
+        match stream {
+            StreamKind::Stderr => self.stderr && !tty,
+            _ => self.stdout || (tty && self.stderr),
+        }
+    }
+
+    fn query(&self, stream: StreamKind, tty: bool, follow: bool) -> String {
+        let mut query = match stream {
+            _ if tty => "stdout=1&stderr=1".to_owned(),
+            StreamKind::Stderr => "stderr=1".to_owned(),
+            _ => "stdout=1".to_owned(),
+        };
+        if follow {
+            query.push_str("&follow=1");
+        }
+
+        match (&self.since, stream) {
+            (Some(since), _) => query.push_str(&format!("&since={}", since.query_value())),
+            (None, StreamKind::Stderr) if !tty => {
+                query.push_str(&format!("&since={}", unix_secs(SystemTime::now())));
+            }
+            (None, _) => {}
+        }
+
+        if let Some(tail) = self.tail {
+            query.push_str(&format!("&tail={}", tail));
+        }
+
+        if self.timestamps {
+            query.push_str("&timestamps=1");
+        }
+
+        query
+    }
+}
+
+/// Follows a container's stdout and stderr. `ContainerLog<Bytes>` hands out the
+/// body exactly as the daemon sent it, `ContainerLog<LogLine>` demultiplexes it and
+/// splits it into lines.
+#[derive(Debug)]
+pub struct ContainerLog<T = Bytes> {
+    pub id: String,
+    pub handle: JoinHandle<()>,
+    pub stdout: UnboundedReceiver<T>,
+    pub stderr: UnboundedReceiver<T>,
+    readers: Arc<Readers>,
+    /// A line [`ContainerLog::recv_ordered`] took early and still has to hand out.
+    held: Option<T>,
+}
+
+/// State shared by the reader tasks of one [`ContainerLog`].
+#[derive(Debug)]
+struct Readers {
+    /// How many more items may be forwarded, if limited.
+    remaining: Option<AtomicUsize>,
+    /// Flipped to `true` to make every reader stop.
+    stop: watch::Sender<bool>,
+    /// What each reader has done so far, stdout's first.
+    counters: [Counters; 2],
+    /// The transport's count of follows reopened, see [`Transport::reconnects`].
+    reconnects: Arc<AtomicU64>,
+    /// Set once [`ContainerLogOptions::startup_grace`] ran out before any output,
+    /// and cleared when some arrives.
+    silent: watch::Sender<bool>,
+}
+
+/// Running totals of one reader, for [`DockerSystem::metrics_prometheus`].
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    /// Body bytes read from the daemon, frame headers included.
+    pub(crate) bytes: AtomicU64,
+    /// Newline-terminated lines of output in those bytes, whatever the mode.
+    pub(crate) lines: AtomicU64,
+}
+
+impl Readers {
+    fn counters(&self, stream: StreamKind) -> &Counters {
+        match stream {
+            StreamKind::Stderr => &self.counters[1],
+            _ => &self.counters[0],
+        }
+    }
+
+    fn has_output(&self) -> bool {
+        self.counters
+            .iter()
+            .any(|counters| counters.bytes.load(Ordering::Relaxed) > 0)
+    }
+
+    /// Claims up to `wanted` items from the limit, returning how many were granted.
+    fn take(&self, wanted: usize) -> usize {
+        let remaining = match &self.remaining {
+            Some(remaining) => remaining,
+            None => return wanted,
+        };
+
+        let mut granted = 0;
+        let _ = remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+            granted = left.min(wanted);
+            Some(left - granted)
+        });
+
+        if granted < wanted || remaining.load(Ordering::SeqCst) == 0 {
+            let _ = self.stop.send(true);
+        }
+
+        granted
+    }
+}
+
+impl ContainerLog<Bytes> {
+    pub async fn new(id: String) -> Result<Self, DockerError> {
+        Self::with_options(id, Default::default()).await
+    }
+
+    pub async fn with_options(
+        id: String,
+        options: ContainerLogOptions,
+    ) -> Result<Self, DockerError> {
+        Self::open(&Transport::default(), id, &options).await
+    }
+
+    pub(crate) async fn open(
+        transport: &Transport,
+        id: String,
+        options: &ContainerLogOptions,
+    ) -> Result<Self, DockerError> {
+        line_mode_only(options)?;
+
+        options.validate()?;
+        let tty = is_tty(transport, &id).await?;
+
+        Self::open_with(transport, id, options, tty, None, None, |stream| {
+            Plan::follow(
+                options,
+                stream,
+                tty,
+                Box::new(|chunk| chunk.into_iter().collect()),
+            )
+        })
+        .await
+    }
+}
+
+impl ContainerLog<Sequenced> {
+    /// Follows the container like [`ContainerLog::new`], numbering every chunk.
+    pub async fn sequenced(id: String) -> Result<Self, DockerError> {
+        Self::sequenced_with_options(id, Default::default()).await
+    }
+
+    pub async fn sequenced_with_options(
+        id: String,
+        options: ContainerLogOptions,
+    ) -> Result<Self, DockerError> {
+        Self::open_sequenced(&Transport::default(), id, &options).await
+    }
+
+    pub(crate) async fn open_sequenced(
+        transport: &Transport,
+        id: String,
+        options: &ContainerLogOptions,
+    ) -> Result<Self, DockerError> {
+        line_mode_only(options)?;
+
+        options.validate()?;
+        let tty = is_tty(transport, &id).await?;
+
+        Self::open_with(transport, id, options, tty, None, None, |stream| {
+            let mut seq = 0;
+            Plan::follow(
+                options,
+                stream,
+                tty,
+                Box::new(move |chunk| {
+                    chunk
+                        .into_iter()
+                        .map(|data| {
+                            let item = Sequenced { seq, data };
+                            seq += 1;
+                            item
+                        })
+                        .collect()
+                }),
+            )
+        })
+        .await
+    }
+}
+
+impl ContainerLog<Frame> {
+    /// Follows the container's frames as the daemon multiplexed them, for consumers
+    /// that care about the protocol itself. Each frame is delivered whole.
+    pub async fn frames(id: String) -> Result<Self, DockerError> {
+        Self::frames_with_options(id, Default::default()).await
+    }
+
+    pub async fn frames_with_options(
+        id: String,
+        options: ContainerLogOptions,
+    ) -> Result<Self, DockerError> {
+        Self::open_frames(&Transport::default(), id, &options).await
+    }
+
+    pub(crate) async fn open_frames(
+        transport: &Transport,
+        id: String,
+        options: &ContainerLogOptions,
+    ) -> Result<Self, DockerError> {
+        line_mode_only(options)?;
+
+        options.validate()?;
+        let tty = is_tty(transport, &id).await?;
+
+        Self::open_with(transport, id, options, tty, None, None, |stream| {
+            let mut decoder = FrameDecoder::new(tty);
+            Plan::follow(
+                options,
+                stream,
+                tty,
+                Box::new(move |chunk| match chunk {
+                    Some(chunk) => decoder.push_frames(chunk),
+                    None => Vec::new(),
+                }),
+            )
+        })
+        .await
+    }
+}
+
+impl ContainerLog<LogLine> {
+    pub async fn lines(id: String) -> Result<Self, DockerError> {
+        Self::lines_with_options(id, Default::default()).await
+    }
+
+    pub async fn lines_with_options(
+        id: String,
+        options: ContainerLogOptions,
+    ) -> Result<Self, DockerError> {
+        Self::open_lines(&Transport::default(), id, &options).await
+    }
+
+    pub(crate) async fn open_lines(
+        transport: &Transport,
+        id: String,
+        options: &ContainerLogOptions,
+    ) -> Result<Self, DockerError> {
+        Self::open_lines_within(transport, id, options, None).await
+    }
+
+    /// [`ContainerLog::open_lines`], charging every line's message to `budget`.
+    /// Whoever ends up receiving the lines must give them back to it.
+    pub(crate) async fn open_lines_within(
+        transport: &Transport,
+        id: String,
+        options: &ContainerLogOptions,
+        budget: Option<Arc<Budget>>,
+    ) -> Result<Self, DockerError> {
+        options.validate()?;
+        let tty = is_tty(transport, &id).await?;
+        let line_options = LineOptions {
+            max_len: options.read_buffer_bytes,
+            collapse_repeats: options.collapse_repeats,
+            strip_ansi: options.strip_ansi,
+            timestamps: options.timestamps || options.bridge_tail || options.resume,
+            min_level: options.min_level,
+            drop_unstructured: options.drop_unstructured,
+        };
+        let decoder = || line_decoder(id.clone(), tty, line_options);
+        // Resuming and bridging go by the timestamps, so the first request must
+        // ask for them too.
+        let options = &ContainerLogOptions {
+            timestamps: line_options.timestamps,
+            ..options.clone()
+        };
+
+        let mut seams = [None, None];
+        if (options.bridge_tail || options.resume) && options.tail.is_some() {
+            for (seam, stream) in seams
+                .iter_mut()
+                .zip([StreamKind::Stdout, StreamKind::Stderr])
+            {
+                if options.reads(stream, tty) {
+                    *seam =
+                        Some(Seam::fetch(transport, &id, options, stream, tty, decoder()).await?);
+                }
+            }
+        }
+
+        let budget = budget.map(|budget| (budget, LogLine::weigh as Weigh<LogLine>));
+        Self::open_with(
+            transport,
+            id.clone(),
+            options,
+            tty,
+            options.max_lines,
+            budget,
+            |stream| {
+                let seam = match stream {
+                    StreamKind::Stderr => seams[1].take(),
+                    _ => seams[0].take(),
+                };
+                let plan = match seam {
+                    Some(seam) => seam.plan(options, stream, tty, decoder()),
+                    None => Plan::follow(options, stream, tty, decoder()),
+                };
+                if options.resume {
+                    plan.resumable(transport, &id, options, stream, tty, line_options)
+                } else {
+                    plan
+                }
+            },
+        )
+        .await
+    }
+
+    /// Follows a logs body from a request made elsewhere, e.g. with extra headers
+    /// or through a proxy. Both streams can come in the one body: lines are routed
+    /// to `stdout` or `stderr` by the stream they were written to. `tty` must match
+    /// the container's `Config.Tty`, since TTY containers send unframed output.
+    /// A gzip `Content-Encoding` is inflated along the way.
+    ///
+    /// Fails with the daemon's error if `response` isn't a success.
+    pub async fn from_response(
+        id: String,
+        response: Response<Body>,
+        tty: bool,
+    ) -> Result<Self, DockerError> {
+        if !response.status().is_success() {
+            return Err(http_error(response).await);
+        }
+        let response = decoded(response)?;
+
+        let readers = Arc::new(Readers {
+            remaining: None,
+            stop: watch::channel(false).0,
+            counters: Default::default(),
+            reconnects: Default::default(),
+            silent: watch::channel(false).0,
+        });
+        let (lines_tx, mut lines_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (stdout_tx, stdout_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (stderr_tx, stderr_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let reader = read(
+            Some(response),
+            lines_tx,
+            StreamKind::Stdout,
+            tty,
+            line_decoder(id.clone(), tty, Default::default()),
+            Vec::new(),
+            None,
+            None,
+            None,
+            readers.clone(),
+        );
+        let route = async move {
+            while let Some(line) = lines_rx.recv().await {
+                let tx: &UnboundedSender<LogLine> = match line.stream {
+                    StreamKind::Stderr => &stderr_tx,
+                    _ => &stdout_tx,
+                };
+                let _ = tx.send(line);
+            }
+        };
+
+        let handle = tokio::spawn(async move {
+            tokio::join!(reader, route);
+        });
+
+        Ok(Self {
+            id,
+            handle,
+            stdout: stdout_rx,
+            stderr: stderr_rx,
+            readers,
+            held: None,
+        })
+    }
+
+    /// The next line from either stream, `None` once both are closed. When lines
+    /// are waiting on both streams the one logged first comes first.
+    ///
+    /// Stdout and stderr arrive over separate connections, so this can only order
+    /// lines that have both arrived: a stderr line still in flight when a later
+    /// stdout line is handed out stays behind it. Ordering needs
+    /// [`ContainerLogOptions::timestamps`], and is only as fine as the daemon's
+    /// timestamp resolution; lines logged at the same instant keep arrival order.
+    pub async fn recv_ordered(&mut self) -> Option<LogLine> {
+        let first = match self.held.take() {
+            Some(line) => line,
+            None => self.recv_any().await?,
+        };
+        let other = match first.stream {
+            StreamKind::Stderr => self.stdout.try_recv().ok(),
+            _ => self.stderr.try_recv().ok(),
+        };
+
+        match other {
+            Some(other) if logged_before(&other, &first) => {
+                self.held = Some(first);
+                Some(other)
+            }
+            Some(other) => {
+                self.held = Some(other);
+                Some(first)
+            }
+            None => Some(first),
+        }
+    }
+
+    /// Follows stdout and stderr, interleaved as by [`ContainerLog::recv_ordered`],
+    /// until a line containing `pattern` arrives and returns it, e.g. to wait for
+    /// "server started" before a test goes on. Fails with
+    /// [`DockerError::TimedOut`] when none has within `timeout`, or with
+    /// [`DockerError::NoOutput`] if the container hasn't logged anything at all
+    /// since its [`ContainerLogOptions::startup_grace`] ran out, and with
+    /// [`DockerError::LogEnded`] when the container stops first. The log goes on
+    /// following afterwards; call [`ContainerLog::stop`] if nothing else is needed.
+    pub async fn wait_for(
+        &mut self,
+        pattern: &str,
+        timeout: Duration,
+    ) -> Result<LogLine, DockerError> {
+        let found = tokio::time::timeout(timeout, async {
+            while let Some(line) = self.recv_ordered().await {
+                if line.message.contains(pattern) {
+                    return Some(line);
+                }
+            }
+            None
+        })
+        .await;
+
+        match found {
+            Ok(Some(line)) => Ok(line),
+            Ok(None) => Err(DockerError::LogEnded),
+            Err(_) if self.is_waiting_for_output() => Err(DockerError::NoOutput(timeout)),
+            Err(_) => Err(DockerError::TimedOut(timeout)),
+        }
+    }
+
+    /// Writes every line, stdout and stderr interleaved as by
+    /// [`ContainerLog::recv_ordered`], to `writer`
+    /// until the container stops, returning how many bytes were written.
+    ///
+    /// The reading end going away (`| head`) ends the pipe the way it would for any
+    /// unix tool: following stops and the bytes written so far are returned.
+    pub async fn pipe_to<W: AsyncWrite + Unpin>(
+        mut self,
+        writer: &mut W,
+    ) -> Result<u64, DockerError> {
+        let mut written = 0;
+
+        while let Some(line) = self.recv_ordered().await {
+            let mut buf = line.message.into_bytes();
+            buf.push(b'\n');
+
+            match writer.write_all(&buf).await {
+                Ok(()) => written += buf.len() as u64,
+                Err(e) => {
+                    self.stop();
+                    return broken_pipe_ok(e, written);
+                }
+            }
+        }
+
+        match writer.flush().await {
+            Ok(()) => Ok(written),
+            Err(e) => broken_pipe_ok(e, written),
+        }
+    }
+
+    /// Mirrors stderr into the app's own tracing output, a `tracing::error!` per
+    /// line carrying the container's id as `container`, until the container stops.
+    /// The `stderr` receiver is taken over and left closed; `stdout` is read as
+    /// usual meanwhile.
+    pub fn stderr_to_tracing(&mut self) -> JoinHandle<()> {
+        let (_, closed) = tokio::sync::mpsc::unbounded_channel();
+        let mut stderr = std::mem::replace(&mut self.stderr, closed);
+
+        tokio::spawn(async move {
+            while let Some(line) = stderr.recv().await {
+                tracing::error!(container = %line.container_id, "{}", line.message);
+            }
+        })
+    }
+
+    /// Writes every line like [`ContainerLog::pipe_to`] to the file at `path`,
+    /// appending to what is already there, until the container stops. Once a line
+    /// would take the file past `max_bytes` it is rotated first, the way logrotate
+    /// does: `path` becomes `path.1`, `path.1` becomes `path.2` and so on, keeping
+    /// at most `max_files` old files. Lines are never split across files, so one
+    /// longer than `max_bytes` gets a file to itself.
+    ///
+    /// Returns how many bytes were written across all files.
+    pub async fn pipe_to_rotating_file(
+        mut self,
+        path: impl AsRef<Path>,
+        max_bytes: u64,
+        max_files: usize,
+    ) -> Result<u64, DockerError> {
+        let path = path.as_ref();
+        let mut written = 0;
+
+        let result = async {
+            let mut file = append_to(path).await?;
+            let mut size = file.metadata().await?.len();
+
+            while let Some(line) = self.recv_ordered().await {
+                let mut buf = line.message.into_bytes();
+                buf.push(b'\n');
+
+                if size > 0 && size + buf.len() as u64 > max_bytes {
+                    file.flush().await?;
+                    rotate(path, max_files).await?;
+                    file = append_to(path).await?;
+                    size = 0;
+                }
+
+                file.write_all(&buf).await?;
+                size += buf.len() as u64;
+                written += buf.len() as u64;
+            }
+
+            file.flush().await
+        }
+        .await;
+
+        match result {
+            Ok(()) => Ok(written),
+            Err(e) => {
+                self.stop();
+                Err(e.into())
+            }
+        }
+    }
+}
+
+fn line_decoder(container_id: String, tty: bool, options: LineOptions) -> Decoder<LogLine> {
+    let mut decoder = LineDecoder::new(container_id, tty, options);
+    Box::new(move |chunk| match chunk {
+        Some(chunk) => decoder.push(chunk),
+        None => decoder.finish(),
+    })
+}
+
+fn drain<T>(rx: &mut UnboundedReceiver<T>) -> Vec<T> {
+    std::iter::from_fn(|| rx.try_recv().ok()).collect()
+}
+
+fn logged_before(a: &LogLine, b: &LogLine) -> bool {
+    match (a.timestamp, b.timestamp) {
+        (Some(a), Some(b)) => a < b,
+        _ => false,
+    }
+}
+
+/// Rejects the options that only make sense once the body is split into lines.
+fn line_mode_only(options: &ContainerLogOptions) -> Result<(), DockerError> {
+    let option = if options.max_lines.is_some() {
+        "max_lines"
+    } else if options.collapse_repeats {
+        "collapse_repeats"
+    } else if options.strip_ansi {
+        "strip_ansi"
+    } else if options.bridge_tail {
+        "bridge_tail"
+    } else if options.min_level.is_some() {
+        "min_level"
+    } else if options.resume {
+        "resume"
+    } else {
+        return Ok(());
+    };
+
+    Err(DockerError::InvalidConfig(format!(
+        "{} needs line mode, use ContainerLog::lines_with_options",
+        option
+    )))
+}
+
+async fn append_to(path: &Path) -> io::Result<tokio::fs::File> {
+    tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+/// `path` with `.n` added, the name its `n`th most recent rotation goes by.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Shifts every rotated file up by one, dropping the oldest, and moves `path`
+/// into its place as `path.1`.
+async fn rotate(path: &Path, max_files: usize) -> io::Result<()> {
+    if max_files == 0 {
+        return tokio::fs::remove_file(path).await;
+    }
+
+    for n in (1..max_files).rev() {
+        match tokio::fs::rename(rotated(path, n), rotated(path, n + 1)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    tokio::fs::rename(path, rotated(path, 1)).await
+}
+
+fn broken_pipe_ok(e: io::Error, written: u64) -> Result<u64, DockerError> {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        Ok(written)
+    } else {
+        Err(e.into())
+    }
+}
+
+/// How one stream's reader starts: the request it makes, what it delivers before
+/// anything it reads, and how it decodes the body.
+struct Plan<T> {
+    query: String,
+    history: Vec<T>,
+    decoder: Decoder<T>,
+    reconnect: Option<Reconnect<T>>,
+}
+
+impl<T> Plan<T> {
+    fn follow(
+        options: &ContainerLogOptions,
+        stream: StreamKind,
+        tty: bool,
+        decoder: Decoder<T>,
+    ) -> Self {
+        Self {
+            query: options.query(stream, tty, true),
+            history: Vec::new(),
+            decoder,
+            reconnect: None,
+        }
+    }
+}
+
+impl Plan<LogLine> {
+    /// Picks the follow back up after the last line it delivered whenever its
+    /// connection drops while the container is running, see
+    /// [`ContainerLogOptions::resume`]. Until a line has been delivered the
+    /// original request is simply made again.
+    fn resumable(
+        mut self,
+        transport: &Transport,
+        id: &str,
+        options: &ContainerLogOptions,
+        stream: StreamKind,
+        tty: bool,
+        line_options: LineOptions,
+    ) -> Self {
+        let mark = Arc::new(Mutex::new(Mark::default()));
+        mark.lock().unwrap().record(&self.history);
+        self.decoder = Mark::recording(mark.clone(), self.decoder);
+
+        let (transport, id, first) = (transport.clone(), id.to_owned(), self.query.clone());
+        let options = options.clone();
+        self.reconnect = Some(Box::new(move || {
+            let (transport, id, first) = (transport.clone(), id.clone(), first.clone());
+            let (options, mark) = (options.clone(), mark.clone());
+
+            Box::pin(async move {
+                let mut delay = RECONNECT_DELAY;
+                loop {
+                    // Also tells a body that ended because the container stopped
+                    // apart from one the daemon cut short.
+                    let running = DockerSystem::unrefreshed(transport.clone())
+                        .is_running(&id)
+                        .await;
+                    if let Ok(false) = running {
+                        return None;
+                    }
+
+                    if running.is_ok() {
+                        let delivered = mark.lock().unwrap().clone();
+                        let query = match delivered.since() {
+                            Some(since) => ContainerLogOptions {
+                                since: Some(since),
+                                tail: None,
+                                timestamps: true,
+                                ..options.clone()
+                            }
+                            .query(stream, tty, true),
+                            None => first.clone(),
+                        };
+                        if let Ok(response) = open_logs(&transport, &id, &query).await {
+                            let decoder = delivered.skip(line_decoder(id, tty, line_options));
+                            return Some((response, Mark::recording(mark, decoder)));
+                        }
+                    }
+
+                    tracing::debug!(container = %id, ?delay, "daemon unreachable, retrying the follow");
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            })
+        }));
+
+        self
+    }
+}
+
+/// The last line a stream delivered, as far as timestamps tell, for following
+/// on right after it.
+#[derive(Debug, Clone, Default)]
+struct Mark {
+    last: Option<SystemTime>,
+    /// How many lines were logged at exactly `last`.
+    seen_at_last: usize,
+}
+
+impl Mark {
+    fn record(&mut self, lines: &[LogLine]) {
+        for at in lines.iter().filter_map(|line| line.timestamp) {
+            if Some(at) == self.last {
+                self.seen_at_last += 1;
+            } else if Some(at) > self.last {
+                self.last = Some(at);
+                self.seen_at_last = 1;
+            }
+        }
+    }
+
+    /// Where a follow picking up after the mark starts, if anything was delivered.
+    fn since(&self) -> Option<Since> {
+        Some(Since::Raw(epoch_seconds(self.last?)))
+    }
+
+    /// `decoder` for a follow from [`Mark::since`]. `since` includes lines logged
+    /// at exactly that instant, so as many of those as were delivered are skipped.
+    fn skip(&self, mut decoder: Decoder<LogLine>) -> Decoder<LogLine> {
+        let (last, mut seen_at_last) = (self.last, self.seen_at_last);
+        Box::new(move |chunk| {
+            let mut lines = decoder(chunk);
+            lines.retain(|line| match (line.timestamp, last) {
+                (Some(at), Some(last)) if at < last => false,
+                (Some(at), Some(last)) if at == last && seen_at_last > 0 => {
+                    seen_at_last -= 1;
+                    false
+                }
+                _ => true,
+            });
+            lines
+        })
+    }
+
+    /// `decoder`, keeping `mark` at the last line it hands out.
+    fn recording(mark: Arc<Mutex<Mark>>, mut decoder: Decoder<LogLine>) -> Decoder<LogLine> {
+        Box::new(move |chunk| {
+            let lines = decoder(chunk);
+            mark.lock().unwrap().record(&lines);
+            lines
+        })
+    }
+}
+
+/// A stream's tail, fetched ahead of its follow for [`ContainerLogOptions::bridge_tail`].
+struct Seam {
+    history: Vec<LogLine>,
+    /// Where the tail was taken from. Without a `since` of its own stderr would
+    /// only ask for what comes next, so the tail covers the whole log instead.
+    since: Since,
+}
+
+impl Seam {
+    async fn fetch(
+        transport: &Transport,
+        id: &str,
+        options: &ContainerLogOptions,
+        stream: StreamKind,
+        tty: bool,
+        mut decoder: Decoder<LogLine>,
+    ) -> Result<Self, DockerError> {
+        let since = options.since.clone().unwrap_or(Since::Raw("0".into()));
+        let snapshot = ContainerLogOptions {
+            since: Some(since.clone()),
+            timestamps: true,
+            ..options.clone()
+        };
+        let response = open_logs(transport, id, &snapshot.query(stream, tty, false)).await?;
+        let body = read_body(response).await?;
+
+        let mut history = decoder(Some(body.into()));
+        history.extend(decoder(None));
+        Ok(Self { history, since })
+    }
+
+    /// Follows on from the last line of the tail. An empty tail had nothing to
+    /// show, so the follow starts where the tail did.
+    fn plan(
+        self,
+        options: &ContainerLogOptions,
+        stream: StreamKind,
+        tty: bool,
+        decoder: Decoder<LogLine>,
+    ) -> Plan<LogLine> {
+        let mut mark = Mark::default();
+        mark.record(&self.history);
+
+        let follow = ContainerLogOptions {
+            since: Some(mark.since().unwrap_or(self.since)),
+            tail: None,
+            timestamps: true,
+            ..options.clone()
+        };
+
+        Plan {
+            query: follow.query(stream, tty, true),
+            history: self.history,
+            decoder: mark.skip(decoder),
+            reconnect: None,
+        }
+    }
+}
+
+impl<T> ContainerLog<T> {
+    /// Whether the readers are done, without waiting on [`ContainerLog::handle`].
+    /// Lines they forwarded before finishing may still be waiting in the receivers.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// What the reader of `stream` has done so far. A TTY container's output, and
+    /// that of [`ContainerLog::from_response`], is all counted as stdout.
+    pub(crate) fn counters(&self, stream: StreamKind) -> &Counters {
+        self.readers.counters(stream)
+    }
+
+    /// Everything stdout has queued up right now, without waiting for more: for
+    /// render loops that take whatever arrived since the last tick. Empty when
+    /// nothing is waiting, and also once the stream has ended; see
+    /// [`ContainerLog::is_finished`] to tell the two apart.
+    pub fn try_drain_stdout(&mut self) -> Vec<T> {
+        drain(&mut self.stdout)
+    }
+
+    /// [`ContainerLog::try_drain_stdout`] for stderr.
+    pub fn try_drain_stderr(&mut self) -> Vec<T> {
+        drain(&mut self.stderr)
+    }
+}
+
+impl<T: Send + 'static> ContainerLog<T> {
+    /// Opens the requested streams the way `plan` says to. A TTY container's
+    /// output all comes through stdout, from a single request.
+    async fn open_with(
+        transport: &Transport,
+        id: String,
+        options: &ContainerLogOptions,
+        tty: bool,
+        max_items: Option<usize>,
+        budget: Option<(Arc<Budget>, Weigh<T>)>,
+        mut plan: impl FnMut(StreamKind) -> Plan<T>,
+    ) -> Result<Self, DockerError> {
+        let mut start = |stream| {
+            let Plan {
+                query,
+                history,
+                decoder,
+                reconnect,
+            } = plan(stream);
+            let transport = transport.clone();
+            let id = id.clone();
+            async move {
+                let response = open_logs(&transport, &id, &query).await?;
+                Ok::<_, DockerError>((Some(response), history, decoder, reconnect))
+            }
+        };
+        let idle = || {
+            (
+                None,
+                Vec::new(),
+                Box::new(|_| Vec::new()) as Decoder<T>,
+                None,
+            )
+        };
+
+        let (stdout_response, stdout_history, stdout_decoder, stdout_reconnect) =
+            if options.reads(StreamKind::Stdout, tty) {
+                start(StreamKind::Stdout).await?
+            } else {
+                idle()
+            };
+        let (stdout_tx, stdout_rx) = tokio::sync::mpsc::unbounded_channel::<T>();
+
+        let (stderr_response, stderr_history, stderr_decoder, stderr_reconnect) =
+            if options.reads(StreamKind::Stderr, tty) {
+                start(StreamKind::Stderr).await?
+            } else {
+                idle()
+            };
+        let (stderr_tx, stderr_rx) = tokio::sync::mpsc::unbounded_channel::<T>();
+
+        let readers = Arc::new(Readers {
+            remaining: max_items.map(AtomicUsize::new),
+            stop: watch::channel(false).0,
+            counters: Default::default(),
+            reconnects: transport.reconnects().clone(),
+            silent: watch::channel(false).0,
+        });
+
+        let buffer = options.read_buffer_bytes;
+        let stdout = read(
+            stdout_response,
+            stdout_tx,
+            StreamKind::Stdout,
+            tty,
+            stdout_decoder,
+            stdout_history,
+            stdout_reconnect,
+            buffer,
+            budget.clone(),
+            readers.clone(),
+        );
+        let stderr = read(
+            stderr_response,
+            stderr_tx,
+            StreamKind::Stderr,
+            tty,
+            stderr_decoder,
+            stderr_history,
+            stderr_reconnect,
+            buffer,
+            budget,
+            readers.clone(),
+        );
+
+        let grace = options
+            .startup_grace
+            .map(|grace| tokio::spawn(watch_startup(id.clone(), grace, readers.clone())));
+        let handle = tokio::spawn(async move {
+            try_join!(tokio::spawn(stdout), tokio::spawn(stderr)).unwrap();
+            if let Some(grace) = grace {
+                grace.abort();
+            }
+        });
+
+        Ok(Self {
+            id,
+            handle,
+            stdout: stdout_rx,
+            stderr: stderr_rx,
+            readers,
+            held: None,
+        })
+    }
+
+    /// The next item from either stream, `None` once both are closed.
+    pub(crate) async fn recv_any(&mut self) -> Option<T> {
+        tokio::select! {
+            Some(item) = self.stdout.recv() => Some(item),
+            Some(item) = self.stderr.recv() => Some(item),
+            else => None,
+        }
+    }
+
+    /// Whether [`ContainerLogOptions::startup_grace`] passed without a byte from
+    /// the container, and none has arrived since.
+    pub fn is_waiting_for_output(&self) -> bool {
+        *self.readers.silent.borrow()
+    }
+
+    /// [`ContainerLog::is_waiting_for_output`] as it changes: it becomes `true`
+    /// when the startup grace runs out with nothing read, and `false` again with
+    /// the first output after that, e.g. to show "attached, waiting for output"
+    /// next to a follow while it lasts.
+    pub fn waiting_for_output(&self) -> watch::Receiver<bool> {
+        self.readers.silent.subscribe()
+    }
+
+    /// Stops following. Each reader hands out what it still holds, in line mode the
+    /// line it was in the middle of flagged [`LogLine::incomplete`], and closes its
+    /// channel, so receiving goes on until both return `None`.
+    pub fn stop(&self) {
+        let _ = self.readers.stop.send(true);
+    }
+}
+
+/// Warns once when nothing has been read `grace` after the follow of `id` opened.
+async fn watch_startup(id: String, grace: Duration, readers: Arc<Readers>) {
+    let mut stop = readers.stop.subscribe();
+    tokio::select! {
+        _ = tokio::time::sleep(grace) => {}
+        _ = stop.wait_for(|stop| *stop) => return,
+    }
+
+    // Checked under the channel's lock, so output counted just now clears it
+    // again right after rather than being missed.
+    let silent = readers.silent.send_if_modified(|silent| {
+        *silent = !readers.has_output();
+        *silent
+    });
+    if silent {
+        tracing::warn!(container = %id, ?grace, "attached, but no output yet");
+    }
+}
+
+/// Forwards a logs body through `decode` until it ends or the readers are told to
+/// stop, then flushes the decoder either way. With `reconnect`, a body that ends
+/// is replaced by the one it opens for as long as there is one. A stream that
+/// wasn't requested has no response and just closes its channel.
+#[allow(clippy::too_many_arguments)]
+async fn read<T>(
+    response: Option<Response<Body>>,
+    tx: UnboundedSender<T>,
+    stream: StreamKind,
+    tty: bool,
+    mut decode: Decoder<T>,
+    history: Vec<T>,
+    mut reconnect: Option<Reconnect<T>>,
+    buffer: Option<usize>,
+    budget: Option<(Arc<Budget>, Weigh<T>)>,
+    readers: Arc<Readers>,
+) {
+    let mut response = match response {
+        Some(response) => response,
+        None => return,
+    };
+    let mut stop = readers.stop.subscribe();
+    let counters = readers.counters(stream);
+
+    // False once nobody is receiving any more, so there's no point reading on. With
+    // a budget, waits for room before anything is sent.
+    let forward = |items: Vec<T>| {
+        let (tx, budget) = (&tx, &budget);
+        let granted = readers.take(items.len());
+
+        async move {
+            for item in items.into_iter().take(granted) {
+                if let Some((budget, weigh)) = budget {
+                    let admitted = tokio::select! {
+                        admitted = budget.take(weigh(&item)) => admitted,
+                        _ = tx.closed() => false,
+                    };
+                    if !admitted {
+                        return false;
+                    }
+                }
+
+                if tx.send(item).is_err() {
+                    return false;
+                }
+            }
+
+            true
+        }
+    };
+
+    if !forward(history).await {
+        return;
+    }
+
+    // A result read while gathering that belongs to the next round.
+    let mut pending = None;
+    // Whether this reader has cleared `silent` with its first output yet.
+    let mut heard = false;
+    // Follows the frames of the body only to count the lines in it.
+    let mut lines = FrameDecoder::new(tty);
+
+    loop {
+        if *stop.borrow() {
+            break;
+        }
+
+        let data = match pending.take() {
+            Some(data) => data,
+            None => tokio::select! {
+                data = response.data() => data,
+                _ = stop.changed() => break,
+                _ = tx.closed() => return,
+            },
+        };
+
+        // The body is over once there's no more data. Whatever follows the final
+        // chunk (trailers, or a connection cut mid-way) isn't log output.
+        let data = match data {
+            Some(Ok(data)) => {
+                counters
+                    .bytes
+                    .fetch_add(data.len() as u64, Ordering::Relaxed);
+                counters
+                    .lines
+                    .fetch_add(lines.count_lines(data.clone()), Ordering::Relaxed);
+                if !heard && !data.is_empty() {
+                    heard = true;
+                    readers.silent.send_if_modified(std::mem::take);
+                }
+                data
+            }
+            Some(Err(_)) | None => {
+                let resumed = match &mut reconnect {
+                    Some(reconnect) => tokio::select! {
+                        resumed = reconnect() => resumed,
+                        _ = stop.changed() => None,
+                        _ = tx.closed() => return,
+                    },
+                    None => None,
+                };
+                match resumed {
+                    // What the old decoder still holds comes again in the new body.
+                    Some((body, decoder)) => {
+                        readers.reconnects.fetch_add(1, Ordering::Relaxed);
+                        lines = FrameDecoder::new(tty);
+                        response = body;
+                        decode = decoder;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+        };
+
+        match buffer {
+            None => {
+                if !forward(decode(Some(data))).await {
+                    return;
+                }
+            }
+            Some(limit) => {
+                let limit = limit.max(1);
+                let mut gathered = data;
+
+                if gathered.len() < limit {
+                    let mut buf = BytesMut::from(&gathered[..]);
+                    while buf.len() < limit {
+                        match future::poll_once(response.data()).await {
+                            Some(Some(Ok(more))) => {
+                                counters
+                                    .bytes
+                                    .fetch_add(more.len() as u64, Ordering::Relaxed);
+                                counters
+                                    .lines
+                                    .fetch_add(lines.count_lines(more.clone()), Ordering::Relaxed);
+                                buf.extend_from_slice(&more)
+                            }
+                            Some(other) => {
+                                pending = Some(other);
+                                break;
+                            }
+                            None => break,
+                        }
+                    }
+                    gathered = buf.freeze();
+                }
+
+                while !gathered.is_empty() {
+                    let piece = gathered.split_to(limit.min(gathered.len()));
+                    if !forward(decode(Some(piece))).await {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    let _ = forward(decode(None)).await;
+}
+
+/// Starts a request against the logs endpoint. When the daemon refuses because the
+/// container's logging driver can't be read from, the driver is looked up so the
+/// caller gets [`DockerError::LogDriverUnsupported`] instead of a bare status code.
+async fn open_logs(
+    transport: &Transport,
+    id: &str,
+    query: &str,
+) -> Result<Response<Body>, DockerError> {
+    let path = format!("/containers/{}/logs?{}", id, query);
+    tracing::debug!(container = id, curl = %transport.curl(&path), "following logs");
+
+    let response = transport.get(&path).await?;
+
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let error = http_error(response).await;
+    if let DockerError::Http { status, message } = &error {
+        let unsupported = *status == StatusCode::NOT_IMPLEMENTED
+            || (*status == StatusCode::BAD_REQUEST && message.contains("logging driver"));
+
+        if unsupported {
+            if let Ok(driver) = log_driver(transport, id).await {
+                return Err(DockerError::LogDriverUnsupported { driver });
+            }
+        }
+    }
+
+    Err(error)
+}
+
+/// The last `n` lines container `id` logged, stdout and stderr interleaved as
+/// the daemon stored them, without following.
+pub(crate) async fn last_lines(
+    transport: &Transport,
+    id: &str,
+    n: usize,
+) -> Result<Vec<LogLine>, DockerError> {
+    let tty = is_tty(transport, id).await?;
+    let response = open_logs(transport, id, &format!("stdout=1&stderr=1&tail={}", n)).await?;
+    let body = read_body(response).await?;
+
+    let mut decoder = LineDecoder::new(id.to_owned(), tty, Default::default());
+    let mut lines = decoder.push(body.into());
+    lines.extend(decoder.finish());
+    Ok(lines)
+}
+
+async fn log_driver(transport: &Transport, id: &str) -> Result<String, DockerError> {
+    let inspect = transport
+        .get_json(&format!("/containers/{}/json", id))
+        .await?;
+
+    inspect
+        .pointer("/HostConfig/LogConfig/Type")
+        .and_then(|v| v.as_str())
+        .map(str::to_owned)
+        .ok_or_else(|| {
+            DockerError::InvalidResponse("inspect has no HostConfig.LogConfig.Type".into())
+        })
+}
+
+/// TTY containers write a single raw stream instead of multiplexed frames.
+async fn is_tty(transport: &Transport, id: &str) -> Result<bool, DockerError> {
+    let inspect = transport
+        .get_json(&format!("/containers/{}/json", id))
+        .await?;
+
+    Ok(inspect
+        .pointer("/Config/Tty")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use hyper::{
+        body::{Bytes, Sender},
+        Body, Response,
+    };
+    use tokio::io::AsyncReadExt;
+
+    use crate::{
+        budget::Budget,
+        mock::{self, Captured},
+        ContainerLog, ContainerLogOptions, DockerError, Frame, Sequenced, Since, StreamKind,
+    };
+
+    /// A daemon whose logs bodies stay open, stdout starting with `stdout`. The
+    /// body senders, stdout first, are kept so tests can write more or end them.
+    fn held_open(stdout: &'static [u8]) -> (mock::MockDaemon, Arc<Mutex<Vec<Sender>>>) {
+        let open: Arc<Mutex<Vec<Sender>>> = Default::default();
+        let stash = open.clone();
+
+        let daemon = mock::MockDaemon::start(move |req| {
+            if req.uri().path().ends_with("/json") {
+                return mock::json(200, r#"{"Config":{"Tty":false}}"#);
+            }
+
+            let (mut tx, body) = Body::channel();
+            if !stdout.is_empty() && req.uri().query().unwrap_or_default().contains("stdout=1") {
+                tx.try_send_data(mock::frame(1, stdout).into()).unwrap();
+            }
+            stash.lock().unwrap().push(tx);
+            Response::new(body)
+        });
+
+        (daemon, open)
+    }
+
+    #[tokio::test]
+    async fn log_driver_unsupported() {
+        let daemon = mock::MockDaemon::start(|req| match req.uri().path() {
+            "/containers/abc/json" => {
+                mock::json(200, r#"{"HostConfig":{"LogConfig":{"Type":"none"}}}"#)
+            }
+            _ => mock::json(
+                501,
+                r#"{"message":"configured logging driver does not support reading"}"#,
+            ),
+        });
+
+        let err = ContainerLog::open(&daemon.transport(), "abc".into(), &Default::default())
+            .await
+            .unwrap_err();
+
+        match err {
+            DockerError::LogDriverUnsupported { driver } => assert_eq!(driver, "none"),
+            e => panic!("unexpected error: {}", e),
+        }
+        assert!(daemon
+            .requests()
+            .contains(&"/containers/abc/json".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn raw_and_line_logs_share_a_body() {
+        let daemon = mock::MockDaemon::start(|req| {
+            if req.uri().path().ends_with("/json") {
+                mock::json(200, r#"{"Config":{"Tty":false}}"#)
+            } else if req.uri().query().unwrap_or_default().contains("stdout=1") {
+                let mut body = mock::frame(1, b"one\ntw");
+                body.extend(mock::frame(1, b"o\n"));
+                Response::new(Body::from(body))
+            } else {
+                Response::new(Body::empty())
+            }
+        });
+
+        let mut raw = ContainerLog::open(&daemon.transport(), "abc".into(), &Default::default())
+            .await
+            .unwrap();
+        let mut body = Vec::new();
+        while let Some(chunk) = raw.stdout.recv().await {
+            body.extend_from_slice(&chunk);
+        }
+        assert_eq!(body.len(), 2 * 8 + 8);
+
+        let mut lines =
+            ContainerLog::open_lines(&daemon.transport(), "abc".into(), &Default::default())
+                .await
+                .unwrap();
+        let mut messages = Vec::new();
+        while let Some(line) = lines.stdout.recv().await {
+            assert_eq!(line.stream, StreamKind::Stdout);
+            messages.push(line.message);
+        }
+        assert_eq!(messages, vec!["one", "two"]);
+    }
+
+    #[tokio::test]
+    async fn drains_what_is_queued_without_waiting() {
+        let (daemon, open) = held_open(b"one\n");
+        let mut log = ContainerLog::open(&daemon.transport(), "abc".into(), &Default::default())
+            .await
+            .unwrap();
+
+        let mut out = open.lock().unwrap().remove(0);
+        out.send_data(mock::frame(1, b"two\n").into())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let drained = log.try_drain_stdout();
+        let body = drained.concat();
+        assert_eq!(
+            body,
+            [mock::frame(1, b"one\n"), mock::frame(1, b"two\n")].concat()
+        );
+        assert!(log.try_drain_stdout().is_empty());
+        assert!(log.try_drain_stderr().is_empty());
+        assert!(!log.is_finished());
+    }
+
+    #[tokio::test]
+    async fn chunks_are_numbered_per_stream() {
+        let (daemon, open) = held_open(b"one\n");
+        let mut log =
+            ContainerLog::open_sequenced(&daemon.transport(), "abc".into(), &Default::default())
+                .await
+                .unwrap();
+        assert_eq!(log.stdout.recv().await.unwrap().seq, 0);
+
+        let (mut out, mut err) = {
+            let mut open = open.lock().unwrap();
+            (open.remove(0), open.remove(0))
+        };
+        out.send_data(mock::frame(1, b"two\n").into())
+            .await
+            .unwrap();
+        err.send_data(mock::frame(2, b"oops\n").into())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            log.stdout.recv().await.unwrap(),
+            Sequenced {
+                seq: 1,
+                data: mock::frame(1, b"two\n").into()
+            }
+        );
+        assert_eq!(log.stderr.recv().await.unwrap().seq, 0);
+    }
+
+    #[tokio::test]
+    async fn silent_container_is_reported_once_after_the_grace() {
+        let (daemon, open) = held_open(b"");
+        let options = ContainerLogOptions {
+            startup_grace: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let (captured, _guard) = Captured::start();
+        let mut log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+        let mut waiting = log.waiting_for_output();
+        assert!(!*waiting.borrow_and_update());
+        assert!(!log.is_waiting_for_output());
+
+        tokio::time::timeout(Duration::from_secs(5), waiting.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(*waiting.borrow_and_update());
+        assert!(log.is_waiting_for_output());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let output = captured.output();
+        assert_eq!(output.matches("attached, but no output yet").count(), 1);
+        assert!(output.contains("WARN"), "{}", output);
+
+        let mut stdout = open.lock().unwrap().remove(0);
+        stdout
+            .send_data(mock::frame(1, b"late\n").into())
+            .await
+            .unwrap();
+        assert_eq!(log.stdout.recv().await.unwrap().message, "late");
+        waiting.changed().await.unwrap();
+        assert!(!*waiting.borrow());
+        assert!(!log.is_waiting_for_output());
+    }
+
+    #[tokio::test]
+    async fn wait_for_tells_a_silent_container_from_a_missing_line() {
+        let options = ContainerLogOptions {
+            startup_grace: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let timeout = Duration::from_millis(100);
+
+        let (daemon, _open) = held_open(b"");
+        let mut log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+        assert!(matches!(
+            log.wait_for("started", timeout).await,
+            Err(DockerError::NoOutput(after)) if after == timeout
+        ));
+
+        let (daemon, _open) = held_open(b"booting\n");
+        let mut log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+        assert!(matches!(
+            log.wait_for("started", timeout).await,
+            Err(DockerError::TimedOut(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn wait_for_returns_the_first_matching_line() {
+        let (daemon, open) = held_open(b"booting\nserver started on :80\nready\n");
+        let mut log =
+            ContainerLog::open_lines(&daemon.transport(), "abc".into(), &Default::default())
+                .await
+                .unwrap();
+
+        let line = log
+            .wait_for("started", Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(line.message, "server started on :80");
+        assert_eq!(log.stdout.recv().await.unwrap().message, "ready");
+
+        let timeout = Duration::from_millis(50);
+        assert!(matches!(
+            log.wait_for("never", timeout).await,
+            Err(DockerError::TimedOut(after)) if after == timeout
+        ));
+
+        open.lock().unwrap().clear();
+        assert!(matches!(
+            log.wait_for("never", Duration::from_secs(5)).await,
+            Err(DockerError::LogEnded)
+        ));
+    }
+
+    #[tokio::test]
+    async fn max_lines_closes_both_streams() {
+        let (daemon, _open) = held_open(b"1\n2\n3\n4\n5\n");
+
+        let options = ContainerLogOptions {
+            since: Some(Since::At(UNIX_EPOCH + Duration::from_secs(1_700_000_000))),
+            tail: Some(100),
+            max_lines: Some(3),
+            ..Default::default()
+        };
+        let mut log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+
+        let mut messages = Vec::new();
+        while let Some(line) = log.stdout.recv().await {
+            messages.push(line.message);
+        }
+        assert_eq!(messages, vec!["1", "2", "3"]);
+        assert!(log.stderr.recv().await.is_none());
+        // The readers drop their senders just before the task ends.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !log.is_finished() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("readers still running after both streams closed");
+
+        let requests = daemon.requests();
+        assert!(requests.contains(
+            &"/containers/abc/logs?stdout=1&follow=1&since=1700000000&tail=100".to_owned()
+        ));
+    }
+
+    #[tokio::test]
+    async fn stop_flushes_the_partial_line() {
+        let (daemon, _open) = held_open(b"one\ntw");
+        let mut log =
+            ContainerLog::open_lines(&daemon.transport(), "abc".into(), &Default::default())
+                .await
+                .unwrap();
+
+        let first = log.stdout.recv().await.unwrap();
+        assert_eq!((first.message.as_str(), first.incomplete), ("one", false));
+        log.stop();
+
+        let cut = log.stdout.recv().await.unwrap();
+        assert_eq!((cut.message.as_str(), cut.incomplete), ("tw", true));
+        assert!(log.stdout.recv().await.is_none());
+        assert!(log.stderr.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn dropped_receiver_stops_its_reader() {
+        let (daemon, open) = held_open(b"one\n");
+        let log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &Default::default())
+            .await
+            .unwrap();
+        assert!(!log.is_finished());
+        let ContainerLog {
+            handle,
+            stdout,
+            mut stderr,
+            ..
+        } = log;
+        drop(stdout);
+
+        let (mut out, mut err) = {
+            let mut open = open.lock().unwrap();
+            (open.remove(0), open.remove(0))
+        };
+        let _ = out.send_data(mock::frame(1, b"two\n").into()).await;
+        err.send_data(mock::frame(2, b"still read\n").into())
+            .await
+            .unwrap();
+        assert_eq!(stderr.recv().await.unwrap().message, "still read");
+        drop(err);
+
+        // stdout's body is still open, so only giving up on the dropped receiver
+        // lets the task finish.
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("reader kept going without a receiver")
+            .expect("reader task panicked");
+        drop(out);
+    }
+
+    #[tokio::test]
+    async fn follow_requests_are_traced_as_curl_commands() {
+        let (captured, _guard) = Captured::start();
+
+        let (daemon, _open) = held_open(b"");
+        let options = ContainerLogOptions {
+            stderr: false,
+            tail: Some(5),
+            ..Default::default()
+        };
+        ContainerLog::open(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+
+        let output = captured.output();
+        let curl = format!(
+            "curl --unix-socket {} 'http://localhost/containers/abc/logs?stdout=1&follow=1&tail=5'",
+            daemon.socket.display()
+        );
+        assert!(output.contains(&curl), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn stderr_is_mirrored_as_error_events() {
+        let (captured, _guard) = Captured::start();
+
+        let (daemon, open) = held_open(b"fine\n");
+        let mut log =
+            ContainerLog::open_lines(&daemon.transport(), "abc".into(), &Default::default())
+                .await
+                .unwrap();
+        let mirroring = log.stderr_to_tracing();
+        assert!(log.stderr.recv().await.is_none());
+
+        let mut err = open.lock().unwrap().remove(1);
+        err.send_data(mock::frame(2, b"disk full\n").into())
+            .await
+            .unwrap();
+        drop(err);
+        mirroring.await.unwrap();
+        assert_eq!(log.stdout.recv().await.unwrap().message, "fine");
+
+        let output = captured.output();
+        assert!(output.contains("ERROR"), "{}", output);
+        assert!(output.contains("container=\"abc\""), "{}", output);
+        assert!(output.contains("disk full"), "{}", output);
+        assert!(!output.contains("fine"), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn reader_waits_for_budget() {
+        let (daemon, _open) = held_open(b"12345\n67890\nabcde\n");
+        let budget = Arc::new(Budget::new(10));
+        let mut log = ContainerLog::open_lines_within(
+            &daemon.transport(),
+            "abc".into(),
+            &Default::default(),
+            Some(budget.clone()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(log.stdout.recv().await.unwrap().message, "12345");
+        assert_eq!(log.stdout.recv().await.unwrap().message, "67890");
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), log.stdout.recv())
+                .await
+                .is_err(),
+            "read past the budget"
+        );
+
+        budget.give(5);
+        assert_eq!(log.stdout.recv().await.unwrap().message, "abcde");
+    }
+
+    #[tokio::test]
+    async fn pipe_to_stops_on_broken_pipe() {
+        let (daemon, open) = held_open(b"one\ntwo\n");
+        let log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &Default::default())
+            .await
+            .unwrap();
+
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+        let piping = tokio::spawn(async move { log.pipe_to(&mut writer).await });
+
+        let mut first = [0u8; 8];
+        reader.read_exact(&mut first).await.unwrap();
+        assert_eq!(&first, b"one\ntwo\n");
+        drop(reader);
+
+        let mut tx = open.lock().unwrap().remove(0);
+        tx.send_data(mock::frame(1, b"three\n").into())
+            .await
+            .unwrap();
+
+        assert_eq!(piping.await.unwrap().unwrap(), 8);
+    }
+
+    #[tokio::test]
+    async fn rotating_file_keeps_the_newest_files() {
+        let daemon = mock::MockDaemon::start(|req| {
+            if req.uri().path().ends_with("/json") {
+                mock::json(200, r#"{"Config":{"Tty":false}}"#)
+            } else if req.uri().query().unwrap_or_default().contains("stdout=1") {
+                Response::new(mock::frame(1, b"a\nb\nc\nd\ne\nf\ng\nh\n").into())
+            } else {
+                Response::new(Body::empty())
+            }
+        });
+        let log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &Default::default())
+            .await
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc.log");
+        std::fs::write(&path, "old\n").unwrap();
+        let written = log.pipe_to_rotating_file(&path, 4, 2).await.unwrap();
+        assert_eq!(written, 16);
+
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("abc.log"), "g\nh\n");
+        assert_eq!(read("abc.log.1"), "e\nf\n");
+        assert_eq!(read("abc.log.2"), "c\nd\n");
+        assert!(!dir.path().join("abc.log.3").exists());
+    }
+
+    #[tokio::test]
+    async fn gzipped_history_of_a_stopped_container_is_inflated() {
+        use std::io::Write;
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        for i in 0..1000 {
+            gzip.write_all(&mock::frame(1, format!("line {}\n", i).as_bytes()))
+                .unwrap();
+        }
+        let compressed = gzip.finish().unwrap();
+
+        let daemon = mock::MockDaemon::start(move |req| {
+            if req.uri().path().ends_with("/json") {
+                return mock::json(200, r#"{"Config":{"Tty":false},"State":{"Running":false}}"#);
+            }
+            if !req.uri().query().unwrap_or_default().contains("stdout=1") {
+                return Response::new(Body::empty());
+            }
+
+            // Arriving in small pieces, so inflating has to carry state across them.
+            let (mut tx, body) = Body::channel();
+            let chunks = compressed
+                .chunks(100)
+                .map(Bytes::copy_from_slice)
+                .collect::<Vec<_>>();
+            tokio::spawn(async move {
+                for chunk in chunks {
+                    tx.send_data(chunk).await.unwrap();
+                }
+            });
+            Response::builder()
+                .header("Content-Encoding", "gzip")
+                .body(body)
+                .unwrap()
+        });
+
+        let mut log =
+            ContainerLog::open_lines(&daemon.transport(), "abc".into(), &Default::default())
+                .await
+                .unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = log.stdout.recv().await {
+            lines.push(line.message);
+        }
+        assert_eq!(lines.len(), 1000);
+        assert_eq!(lines[0], "line 0");
+        assert_eq!(lines[999], "line 999");
+    }
+
+    #[tokio::test]
+    async fn huge_line_streams_in_bounded_pieces() {
+        const LIMIT: usize = 64 * 1024;
+        let line = [vec![b'x'; 5 * 1024 * 1024], b"\n".to_vec()].concat();
+        let body = mock::frame(1, &line);
+
+        let daemon = mock::MockDaemon::start(move |req| {
+            if req.uri().path().ends_with("/json") {
+                mock::json(200, r#"{"Config":{"Tty":false}}"#)
+            } else if req.uri().query().unwrap_or_default().contains("stdout=1") {
+                Response::new(body.clone().into())
+            } else {
+                Response::new(Body::empty())
+            }
+        });
+        let options = ContainerLogOptions {
+            read_buffer_bytes: Some(LIMIT),
+            ..Default::default()
+        };
+
+        let mut raw = ContainerLog::open(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+        let mut total = 0;
+        while let Some(chunk) = raw.stdout.recv().await {
+            assert!(chunk.len() <= LIMIT);
+            total += chunk.len();
+        }
+        assert_eq!(total, line.len() + 8);
+
+        let mut lines = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+        let mut total = 0;
+        while let Some(piece) = lines.stdout.recv().await {
+            assert!(piece.message.len() <= LIMIT);
+            assert!(piece.message.bytes().all(|b| b == b'x'));
+            total += piece.message.len();
+        }
+        assert_eq!(total, line.len() - 1);
+    }
+
+    #[tokio::test]
+    async fn gathered_chunks_count_their_lines() {
+        // Ready all at once, so the first chunk read gathers the others with it.
+        let chunks = ["one\n", "two\n", "three\n"]
+            .iter()
+            .map(|line| Ok::<_, std::io::Error>(Bytes::from(mock::frame(1, line.as_bytes()))))
+            .collect::<Vec<_>>();
+        let body = Body::wrap_stream(futures_lite::stream::iter(chunks));
+        let readers = Arc::new(super::Readers {
+            remaining: None,
+            stop: tokio::sync::watch::channel(false).0,
+            counters: Default::default(),
+            reconnects: Default::default(),
+            silent: tokio::sync::watch::channel(false).0,
+        });
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        super::read(
+            Some(Response::new(body)),
+            tx,
+            StreamKind::Stdout,
+            false,
+            super::line_decoder("abc".into(), false, Default::default()),
+            Vec::new(),
+            None,
+            Some(1024),
+            None,
+            readers.clone(),
+        )
+        .await;
+        let mut messages = Vec::new();
+        while let Ok(line) = rx.try_recv() {
+            messages.push(line.message);
+        }
+        assert_eq!(messages, vec!["one", "two", "three"]);
+
+        let counters = readers.counters(StreamKind::Stdout);
+        assert_eq!(counters.bytes.load(Ordering::Relaxed), 38);
+        assert_eq!(counters.lines.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn follow_ends_at_final_chunk_despite_trailers() {
+        let daemon = mock::RawDaemon::start(
+            b"HTTP/1.1 200 OK\r\n\
+              Content-Type: application/vnd.docker.raw-stream\r\n\
+              Transfer-Encoding: chunked\r\n\
+              Trailer: X-Docker-Exit\r\n\
+              \r\n\
+              e\r\n\x01\x00\x00\x00\x00\x00\x00\x06hello\n\r\n\
+              0\r\n\
+              X-Docker-Exit: 0\r\n\
+              \r\n",
+        );
+        let options = ContainerLogOptions {
+            stderr: false,
+            ..Default::default()
+        };
+        let mut log = ContainerLog::open(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+
+        let drained = tokio::time::timeout(Duration::from_secs(5), async {
+            let mut body = Vec::new();
+            while let Some(chunk) = log.stdout.recv().await {
+                body.extend_from_slice(&chunk);
+            }
+            body
+        })
+        .await
+        .expect("follow hung after the trailers");
+
+        assert_eq!(drained, mock::frame(1, b"hello\n"));
+        log.handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn from_response_routes_lines_by_stream() {
+        let daemon = mock::MockDaemon::start(|req| match req.uri().path() {
+            "/custom/logs" => {
+                let mut body = mock::frame(1, b"out\n");
+                body.extend(mock::frame(2, b"err\n"));
+                Response::new(body.into())
+            }
+            _ => mock::json(403, r#"{"message":"forbidden by proxy"}"#),
+        });
+        let transport = daemon.transport();
+
+        let response = transport.get("/custom/logs").await.unwrap();
+        let mut log = ContainerLog::from_response("abc".into(), response, false)
+            .await
+            .unwrap();
+
+        let out = log.stdout.recv().await.unwrap();
+        assert_eq!(
+            (out.stream, out.message.as_str()),
+            (StreamKind::Stdout, "out")
+        );
+        let err = log.stderr.recv().await.unwrap();
+        assert_eq!(
+            (err.stream, err.message.as_str()),
+            (StreamKind::Stderr, "err")
+        );
+        assert!(log.stdout.recv().await.is_none());
+
+        let refused = transport.get("/elsewhere").await.unwrap();
+        match ContainerLog::from_response("abc".into(), refused, false).await {
+            Err(DockerError::Http { message, .. }) => assert_eq!(message, "forbidden by proxy"),
+            other => panic!("unexpected result: {:?}", other.map(|log| log.id)),
+        }
+    }
+
+    #[tokio::test]
+    async fn frames_come_whole_per_stream() {
+        let daemon = mock::MockDaemon::start(|req| {
+            if req.uri().path().ends_with("/json") {
+                return mock::json(200, r#"{"Config":{"Tty":false}}"#);
+            }
+            let mut body = if req.uri().query().unwrap_or_default().contains("stdout=1") {
+                mock::frame(1, b"one")
+            } else {
+                mock::frame(2, b"two")
+            };
+            body.extend(mock::frame(1, b"three"));
+            Response::new(body.into())
+        });
+        let options = ContainerLogOptions {
+            read_buffer_bytes: Some(3),
+            ..Default::default()
+        };
+        let mut log = ContainerLog::open_frames(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+
+        let mut stdout = Vec::new();
+        while let Some(frame) = log.stdout.recv().await {
+            stdout.push(frame);
+        }
+        assert_eq!(
+            stdout,
+            vec![
+                Frame {
+                    stream: StreamKind::Stdout,
+                    payload: "one".into()
+                },
+                Frame {
+                    stream: StreamKind::Stdout,
+                    payload: "three".into()
+                },
+            ]
+        );
+        assert_eq!(log.stderr.recv().await.unwrap().stream, StreamKind::Stderr);
+    }
+
+    #[tokio::test]
+    async fn timestamps_order_lines_across_streams() {
+        let daemon = mock::MockDaemon::start(|req| {
+            if req.uri().path().ends_with("/json") {
+                return mock::json(200, r#"{"Config":{"Tty":false}}"#);
+            }
+            let query = req.uri().query().unwrap_or_default();
+            assert!(query.contains("timestamps=1"), "{}", query);
+            let body = if query.contains("stdout=1") {
+                "2024-01-01T00:00:01.000000001Z first\n2024-01-01T00:00:03Z third\n"
+            } else {
+                "2024-01-01T00:00:01.5Z second\n2024-01-01T00:00:04Z fourth\n"
+            };
+            let stream = if query.contains("stdout=1") { 1 } else { 2 };
+            Response::new(mock::frame(stream, body.as_bytes()).into())
+        });
+        let options = ContainerLogOptions {
+            timestamps: true,
+            ..Default::default()
+        };
+        let mut log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+        // Let both streams arrive in full, so there is something to order.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut lines = Vec::new();
+        while let Some(line) = log.recv_ordered().await {
+            lines.push((line.stream, line.message));
+        }
+        assert_eq!(
+            lines,
+            vec![
+                (StreamKind::Stdout, "first".to_owned()),
+                (StreamKind::Stderr, "second".to_owned()),
+                (StreamKind::Stdout, "third".to_owned()),
+                (StreamKind::Stderr, "fourth".to_owned()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn bridged_tail_has_no_gap_or_duplicates() {
+        // Seconds past 2024-01-01T00:00:00Z each line was logged at.
+        const JAN_2024: u64 = 1_704_067_200;
+        let log = Arc::new(Mutex::new(vec![(1, "a"), (2, "b"), (3, "c")]));
+        let written = log.clone();
+        let daemon = mock::MockDaemon::start(move |req| {
+            if req.uri().path().ends_with("/json") {
+                return mock::json(200, r#"{"Config":{"Tty":false}}"#);
+            }
+            let query = req.uri().query().unwrap_or_default();
+            assert!(query.contains("timestamps=1"), "{}", query);
+            let since = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("since="))
+                .and_then(|since| since.split('.').next()?.parse::<u64>().ok())
+                .unwrap();
+            let since = since.saturating_sub(JAN_2024);
+
+            let mut log = written.lock().unwrap();
+            let mut lines: Vec<_> = log.iter().filter(|(at, _)| *at >= since).collect();
+            if !query.contains("follow=1") {
+                let tail = lines.len() - 2;
+                lines.drain(..tail);
+            }
+            let body: String = lines
+                .iter()
+                .map(|(at, line)| format!("2024-01-01T00:00:{:02}Z {}\n", at, line))
+                .collect();
+            if !query.contains("follow=1") {
+                // Written while the follow is being set up, one of them in the same
+                // second as the last line of the tail.
+                log.extend([(3, "c2"), (4, "d")]);
+            }
+            Response::new(mock::frame(1, body.as_bytes()).into())
+        });
+        let options = ContainerLogOptions {
+            stderr: false,
+            tail: Some(2),
+            bridge_tail: true,
+            ..Default::default()
+        };
+        let mut log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+
+        let mut lines = Vec::new();
+        while let Some(line) = log.stdout.recv().await {
+            lines.push(line.message);
+        }
+        assert_eq!(lines, vec!["b", "c", "c2", "d"]);
+        let requests = daemon.requests();
+        assert!(requests[1].contains("since=0&tail=2"), "{:?}", requests);
+        assert!(
+            requests[2].contains("follow=1&since=1704067203.000000000&timestamps=1"),
+            "{:?}",
+            requests
+        );
+    }
+
+    #[tokio::test]
+    async fn resumed_follow_continues_across_a_disconnect() {
+        let inspects = Arc::new(AtomicUsize::new(0));
+        let seen = inspects.clone();
+        let daemon = mock::MockDaemon::start(move |req| {
+            if req.uri().path().ends_with("/json") {
+                // Looked up on opening and after the disconnect, then it exits.
+                let running = seen.fetch_add(1, Ordering::SeqCst) < 2;
+                return mock::json(
+                    200,
+                    &format!(
+                        r#"{{"Config":{{"Tty":false}},"State":{{"Running":{}}}}}"#,
+                        running
+                    ),
+                );
+            }
+
+            let line = |at: u32, message: &str| format!("2024-01-01T00:00:0{}Z {}\n", at, message);
+            let query = req.uri().query().unwrap_or_default();
+            if !query.contains("follow=1") {
+                let tail = line(0, "zero") + &line(1, "one");
+                return Response::new(mock::frame(1, tail.as_bytes()).into());
+            }
+            if query.contains("since=1704067201.") {
+                // The daemon goes away in the middle of "three".
+                let (mut tx, body) = Body::channel();
+                let sent = line(1, "one") + &line(2, "two") + "2024-01-01T00:00:03Z thr";
+                tokio::spawn(async move {
+                    tx.send_data(mock::frame(1, sent.as_bytes()).into())
+                        .await
+                        .unwrap();
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    tx.abort();
+                });
+                return Response::new(body);
+            }
+
+            let rest = line(2, "two") + &line(3, "three") + &line(4, "four");
+            Response::new(mock::frame(1, rest.as_bytes()).into())
+        });
+        let options = ContainerLogOptions {
+            stderr: false,
+            tail: Some(2),
+            resume: true,
+            ..Default::default()
+        };
+        let mut log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+
+        let mut lines = Vec::new();
+        while let Some(line) = log.stdout.recv().await {
+            assert!(!line.incomplete, "{:?}", line);
+            lines.push(line.message);
+        }
+        assert_eq!(lines, vec!["zero", "one", "two", "three", "four"]);
+
+        let requests = daemon.requests();
+        assert!(
+            requests[4].contains("follow=1&since=1704067202.000000000&timestamps=1"),
+            "{:?}",
+            requests
+        );
+        assert_eq!(requests.len(), 6, "{:?}", requests);
+    }
+
+    #[tokio::test]
+    async fn resumed_follow_without_a_tail_picks_up_after_the_last_line() {
+        let daemon = mock::MockDaemon::start(|req| {
+            if req.uri().path().ends_with("/json") {
+                return mock::json(200, r#"{"Config":{"Tty":false},"State":{"Running":true}}"#);
+            }
+
+            let query = req.uri().query().unwrap_or_default();
+            let (mut tx, body) = Body::channel();
+            let (sent, cut) = if query.contains("since=1704067201.") {
+                ("2024-01-01T00:00:02Z two\n", false)
+            } else if query.contains("timestamps=1") {
+                ("2024-01-01T00:00:01Z one\n", true)
+            } else {
+                ("one\n", true)
+            };
+            tokio::spawn(async move {
+                tx.send_data(mock::frame(1, sent.as_bytes()).into())
+                    .await
+                    .unwrap();
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                if cut {
+                    tx.abort();
+                } else {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                }
+            });
+            Response::new(body)
+        });
+        let options = ContainerLogOptions {
+            stderr: false,
+            resume: true,
+            ..Default::default()
+        };
+        let mut log = ContainerLog::open_lines(&daemon.transport(), "abc".into(), &options)
+            .await
+            .unwrap();
+
+        assert_eq!(log.stdout.recv().await.unwrap().message, "one");
+        assert_eq!(log.stdout.recv().await.unwrap().message, "two");
+        let follows = daemon
+            .requests()
+            .into_iter()
+            .filter(|r| r.contains("/logs"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            follows,
+            vec![
+                "/containers/abc/logs?stdout=1&follow=1&timestamps=1",
+                "/containers/abc/logs?stdout=1&follow=1&since=1704067201.000000000&timestamps=1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn tty_output_is_followed_once() {
+        let daemon = mock::MockDaemon::start(|req| {
+            if req.uri().path().ends_with("/json") {
+                return mock::json(200, r#"{"Config":{"Tty":true}}"#);
+            }
+            Response::new("$ make\nok\n".into())
+        });
+
+        let mut log =
+            ContainerLog::open_lines(&daemon.transport(), "abc".into(), &Default::default())
+                .await
+                .unwrap();
+        let mut lines = Vec::new();
+        while let Some(line) = log.recv_any().await {
+            lines.push((line.stream, line.message));
+        }
+        assert_eq!(
+            lines,
+            vec![
+                (StreamKind::Stdout, "$ make".to_owned()),
+                (StreamKind::Stdout, "ok".to_owned())
+            ]
+        );
+
+        let stderr_only = ContainerLogOptions {
+            stdout: false,
+            ..Default::default()
+        };
+        let mut frames = ContainerLog::open_frames(&daemon.transport(), "abc".into(), &stderr_only)
+            .await
+            .unwrap();
+        assert!(frames.stdout.recv().await.is_some());
+        assert!(frames.stderr.recv().await.is_none());
+
+        let follows = daemon
+            .requests()
+            .into_iter()
+            .filter(|r| r.contains("/logs?"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            follows,
+            vec![
+                "/containers/abc/logs?stdout=1&stderr=1&follow=1",
+                "/containers/abc/logs?stdout=1&stderr=1&follow=1"
+            ]
+        );
+    }
+
+    #[test]
+    fn since_before_the_epoch_is_clamped() {
+        assert_eq!(Since::Ago(Duration::MAX).query_value(), "0");
+        assert_eq!(
+            Since::At(UNIX_EPOCH - Duration::from_secs(60)).query_value(),
+            "0"
+        );
+        assert_eq!(
+            Since::At(UNIX_EPOCH + Duration::from_secs(60)).query_value(),
+            "60"
+        );
+    }
+
+    #[tokio::test]
+    async fn since_strings_are_converted_to_seconds() {
+        let (daemon, _open) = held_open(b"");
+        let open = |since: &str| {
+            let options = ContainerLogOptions {
+                since: Some(Since::Raw(since.into())),
+                stderr: false,
+                ..Default::default()
+            };
+            let transport = daemon.transport();
+            async move { ContainerLog::open(&transport, "abc".into(), &options).await }
+        };
+
+        for since in [
+            "2024-01-01T00:00:00+01:00",
+            "2024-01-01T00:00:00.25Z",
+            "2024-01-01T01:00:00",
+            "2024-01-01",
+            "2024-01-02-01:00",
+            "1704067200.5",
+            "1h30m",
+        ] {
+            open(since).await.unwrap();
+        }
+        let follows = || {
+            daemon
+                .requests()
+                .into_iter()
+                .filter(|r| r.contains("/logs"))
+                .map(|r| r.split("since=").nth(1).unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let mut sent = follows();
+        let ago = sent.pop().unwrap();
+        assert_eq!(
+            sent,
+            vec![
+                "1704063600.000000000",
+                "1704067200.250000000",
+                "1704070800.000000000",
+                "1704067200.000000000",
+                "1704157200.000000000",
+                "1704067200.5",
+            ]
+        );
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let ago = ago.parse::<f64>().unwrap();
+        assert!((now.as_secs_f64() - 5_400.0 - ago).abs() < 60.0, "{}", ago);
+
+        let asked = daemon.requests().len();
+        for bad in [
+            "",
+            "yesterday",
+            "2024-01-01 00:00:00",
+            "1&tail=5",
+            "10x",
+            "2024-13-01",
+        ] {
+            assert!(matches!(
+                open(bad).await,
+                Err(DockerError::InvalidConfig(_))
+            ));
+        }
+        // Turned away without even inspecting the container.
+        assert_eq!(daemon.requests().len(), asked);
+    }
+}
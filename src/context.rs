@@ -0,0 +1,241 @@
+//! Reading the endpoint of a `docker context` from the CLI's configuration.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::{DockerConfig, DockerError, TcpEndpoint, TlsConfig};
+
+impl DockerConfig {
+    /// The default config pointed at the daemon of the CLI context `name`, as
+    /// `docker --context name` would talk to it. Contexts are read from
+    /// `$DOCKER_CONFIG/contexts`, or `~/.docker/contexts` without it, and
+    /// `default` is the default socket.
+    ///
+    /// A context on `tcp://` connects over TCP, with TLS when the context has
+    /// certificates stored with it (`docker context create --docker
+    /// ca=...,cert=...,key=...`) or skips verifying them. One on `ssh://` is a
+    /// [`DockerError::InvalidConfig`].
+    pub fn from_context(name: &str) -> Result<Self, DockerError> {
+        let dir = match std::env::var_os("DOCKER_CONFIG") {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let home = std::env::var_os("HOME").ok_or_else(|| {
+                    DockerError::InvalidConfig("HOME is not set to look up contexts in".into())
+                })?;
+                Path::new(&home).join(".docker")
+            }
+        };
+
+        context_config(&dir, name)
+    }
+}
+
+/// The config for context `name` in the CLI config directory `dir`.
+fn context_config(dir: &Path, name: &str) -> Result<DockerConfig, DockerError> {
+    if name == "default" {
+        return Ok(DockerConfig::default());
+    }
+
+    // Each context lives in a directory named after a hash of its name, so find
+    // it by the name recorded inside instead. One that can't be read or parsed is
+    // someone else's problem and doesn't stop the search.
+    let contexts = dir.join("contexts");
+    let meta = contexts.join("meta");
+    let entries = fs::read_dir(&meta).map_err(|e| {
+        DockerError::InvalidConfig(format!("no contexts in {}: {}", meta.display(), e))
+    })?;
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let value = match fs::read(entry.path().join("meta.json"))
+            .ok()
+            .and_then(|json| serde_json::from_slice::<Value>(&json).ok())
+        {
+            Some(value) => value,
+            None => continue,
+        };
+        if value.get("Name").and_then(Value::as_str) != Some(name) {
+            continue;
+        }
+
+        let endpoint = value.pointer("/Endpoints/docker");
+        let host = endpoint
+            .and_then(|endpoint| endpoint.get("Host"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| {
+                DockerError::InvalidConfig(format!("context {:?} has no docker endpoint", name))
+            })?;
+
+        if let Some(socket) = host.strip_prefix("unix://") {
+            return Ok(DockerConfig {
+                socket: PathBuf::from(socket),
+                ..Default::default()
+            });
+        }
+        let address = host.strip_prefix("tcp://").ok_or_else(|| {
+            DockerError::InvalidConfig(format!(
+                "context {:?} points at {}, only unix sockets and tcp are supported",
+                name, host
+            ))
+        })?;
+
+        let skip_verify = endpoint
+            .and_then(|endpoint| endpoint.get("SkipTLSVerify"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let certs = contexts.join("tls").join(entry.file_name()).join("docker");
+        let stored = |file: &str| Some(certs.join(file)).filter(|path| path.is_file());
+        let tls = TlsConfig {
+            ca: stored("ca.pem"),
+            cert: stored("cert.pem"),
+            key: stored("key.pem"),
+            skip_verify,
+        };
+        let tls = Some(tls).filter(|tls| *tls != TlsConfig::default());
+
+        return Ok(DockerConfig {
+            tcp: Some(tcp_endpoint(name, address, tls)?),
+            ..Default::default()
+        });
+    }
+
+    Err(DockerError::InvalidConfig(format!(
+        "no context named {:?}",
+        name
+    )))
+}
+
+/// `host[:port]`, on the port the daemon listens on by default when it's left out.
+fn tcp_endpoint(
+    name: &str,
+    address: &str,
+    tls: Option<TlsConfig>,
+) -> Result<TcpEndpoint, DockerError> {
+    let invalid =
+        || DockerError::InvalidConfig(format!("context {:?} has a bad tcp address", name));
+    let address = address.trim_end_matches('/');
+    let default_port = if tls.is_some() { 2376 } else { 2375 };
+
+    // An IPv6 host is in brackets when a port follows it.
+    let (host, port) = match address.strip_prefix('[') {
+        Some(rest) => {
+            let (host, rest) = rest.split_once(']').ok_or_else(invalid)?;
+            match rest.strip_prefix(':') {
+                Some(port) => (host, port.parse().map_err(|_| invalid())?),
+                None if rest.is_empty() => (host, default_port),
+                None => return Err(invalid()),
+            }
+        }
+        None => match address.split_once(':') {
+            Some((host, port)) if !port.contains(':') => {
+                (host, port.parse().map_err(|_| invalid())?)
+            }
+            _ => (address, default_port),
+        },
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(TcpEndpoint {
+        host: host.to_owned(),
+        port,
+        tls,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::context_config;
+    use crate::{DockerError, TcpEndpoint, TlsConfig, DEFAULT_DOCKER_SOCKET};
+
+    #[test]
+    fn contexts_resolve_to_their_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let contexts = [
+            ("rootless", "unix:///run/user/1000/docker.sock", false),
+            ("remote", "tcp://10.0.0.5:2376", false),
+            ("plain", "tcp://build-host", false),
+            ("lab", "tcp://[fd00::5]", true),
+            ("tunnel", "ssh://me@build-host", false),
+        ];
+        for (i, (name, host, skip_verify)) in contexts.iter().enumerate() {
+            let meta = dir.path().join("contexts/meta").join(format!("{:064x}", i));
+            fs::create_dir_all(&meta).unwrap();
+            let json = format!(
+                r#"{{"Name":"{}","Metadata":{{}},"Endpoints":{{"docker":{{"Host":"{}","SkipTLSVerify":{}}}}}}}"#,
+                name, host, skip_verify
+            );
+            fs::write(meta.join("meta.json"), json).unwrap();
+        }
+        let certs = dir
+            .path()
+            .join("contexts/tls")
+            .join(format!("{:064x}", 1))
+            .join("docker");
+        fs::create_dir_all(&certs).unwrap();
+        for file in ["ca.pem", "cert.pem", "key.pem"] {
+            fs::write(certs.join(file), "").unwrap();
+        }
+        // Broken entries next to the real ones are passed over.
+        let broken = dir.path().join("contexts/meta/broken");
+        fs::create_dir_all(&broken).unwrap();
+        fs::write(broken.join("meta.json"), "{not json").unwrap();
+        fs::create_dir_all(dir.path().join("contexts/meta/empty")).unwrap();
+
+        let config = context_config(dir.path(), "rootless").unwrap();
+        assert_eq!(config.socket, PathBuf::from("/run/user/1000/docker.sock"));
+        assert_eq!(config.tcp, None);
+        assert_eq!(
+            context_config(dir.path(), "default").unwrap().socket,
+            PathBuf::from(DEFAULT_DOCKER_SOCKET)
+        );
+        assert_eq!(
+            context_config(dir.path(), "remote").unwrap().tcp,
+            Some(TcpEndpoint {
+                host: "10.0.0.5".into(),
+                port: 2376,
+                tls: Some(TlsConfig {
+                    ca: Some(certs.join("ca.pem")),
+                    cert: Some(certs.join("cert.pem")),
+                    key: Some(certs.join("key.pem")),
+                    skip_verify: false,
+                }),
+            })
+        );
+        assert_eq!(
+            context_config(dir.path(), "plain").unwrap().tcp,
+            Some(TcpEndpoint {
+                host: "build-host".into(),
+                port: 2375,
+                tls: None,
+            })
+        );
+        assert_eq!(
+            context_config(dir.path(), "lab").unwrap().tcp,
+            Some(TcpEndpoint {
+                host: "fd00::5".into(),
+                port: 2376,
+                tls: Some(TlsConfig {
+                    skip_verify: true,
+                    ..Default::default()
+                }),
+            })
+        );
+        for name in ["tunnel", "missing"] {
+            assert!(matches!(
+                context_config(dir.path(), name),
+                Err(DockerError::InvalidConfig(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{error::Error, fmt, path::PathBuf};
+
+use hyper::StatusCode;
+
+#[derive(Debug)]
+pub enum DockerError {
+    /// The request never got a response from the daemon.
+    Transport(hyper::Error),
+    /// Nothing answered on the daemon socket at all.
+    DaemonUnreachable {
+        /// The socket, or `tcp://host:port` for a daemon on TCP.
+        socket: PathBuf,
+        source: hyper::Error,
+    },
+    /// The daemon socket is there but this user may not open it.
+    PermissionDenied { path: PathBuf },
+    /// Reading from the daemon socket failed.
+    Io(std::io::Error),
+    /// The daemon answered with something that isn't the JSON we expected.
+    Json(serde_json::Error),
+    /// The daemon answered with a non-success status code.
+    Http { status: StatusCode, message: String },
+    /// The response parsed but was missing fields we rely on.
+    InvalidResponse(String),
+    /// A configuration or options value can't be used.
+    InvalidConfig(String),
+    /// The container's logging driver can't be read back through the logs endpoint.
+    LogDriverUnsupported { driver: String },
+    /// The operation was called off through its cancellation token.
+    Cancelled,
+    /// What was being waited for didn't happen within this long.
+    TimedOut(std::time::Duration),
+    /// The container's log ended before the line being waited for showed up.
+    LogEnded,
+    /// Like [`DockerError::TimedOut`], but the container hadn't logged anything at
+    /// all past its startup grace.
+    NoOutput(std::time::Duration),
+}
+
+impl fmt::Display for DockerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DockerError::Transport(e) => write!(f, "docker daemon request failed: {}", e),
+            DockerError::DaemonUnreachable { socket, source } => write!(
+                f,
+                "docker daemon unreachable at {}: {}",
+                socket.display(),
+                source
+            ),
+            DockerError::PermissionDenied { path } => write!(
+                f,
+                "permission denied opening the docker socket at {}; \
+                 is this user in the `docker` group? (add it with \
+                 `sudo usermod -aG docker $USER` and log in again)",
+                path.display()
+            ),
+            DockerError::Io(e) => write!(f, "docker socket io error: {}", e),
+            DockerError::Json(e) => write!(f, "invalid json from docker daemon: {}", e),
+            DockerError::Http { status, message } => {
+                write!(f, "docker daemon returned {}: {}", status, message)
+            }
+            DockerError::InvalidResponse(what) => {
+                write!(f, "unexpected response from docker daemon: {}", what)
+            }
+            DockerError::InvalidConfig(what) => write!(f, "invalid configuration: {}", what),
+            DockerError::LogDriverUnsupported { driver } => write!(
+                f,
+                "container uses the `{}` logging driver, which does not support reading logs",
+                driver
+            ),
+            DockerError::Cancelled => write!(f, "cancelled"),
+            DockerError::TimedOut(after) => write!(f, "timed out after {:?}", after),
+            DockerError::LogEnded => write!(f, "the log ended before the line appeared"),
+            DockerError::NoOutput(after) => write!(
+                f,
+                "timed out after {:?} with no output from the container",
+                after
+            ),
+        }
+    }
+}
+
+impl Error for DockerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DockerError::Transport(e) => Some(e),
+            DockerError::DaemonUnreachable { source, .. } => Some(source),
+            DockerError::Io(e) => Some(e),
+            DockerError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<hyper::Error> for DockerError {
+    fn from(e: hyper::Error) -> Self {
+        DockerError::Transport(e)
+    }
+}
+
+impl From<serde_json::Error> for DockerError {
+    fn from(e: serde_json::Error) -> Self {
+        DockerError::Json(e)
+    }
+}
+
+impl From<std::io::Error> for DockerError {
+    fn from(e: std::io::Error) -> Self {
+        DockerError::Io(e)
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use hyper::body::HttpBody;
+
+use crate::{
+    line::{LineSplitter, LogLine},
+    stream::TaskStream,
+    transport::{filters_query, Transport},
+    DockerError, DockerSystem,
+};
+
+/// A container lifecycle change reported by the daemon's `/events` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DockerEvent {
+    Started {
+        id: String,
+        name: String,
+    },
+    Stopped {
+        id: String,
+        name: String,
+        /// When the container died by the daemon's clock, from the event's
+        /// `timeNano` (or `time`), if it said.
+        at: Option<SystemTime>,
+    },
+    /// `docker pause`: the container is frozen, so its logs go quiet, but its
+    /// follow stays open and picks up again once it is unpaused.
+    Paused {
+        id: String,
+        name: String,
+    },
+    Unpaused {
+        id: String,
+        name: String,
+    },
+}
+
+/// Everything happening on a host, in the order the supervisor saw it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostEvent {
+    Lifecycle(DockerEvent),
+    Log(LogLine),
+}
+
+/// What [`DockerSystem::follow_named`] yields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FollowEvent {
+    Log(LogLine),
+    /// A new instance of the followed container started; every line after this
+    /// comes from container `id`.
+    Restarted {
+        name: String,
+        id: String,
+    },
+}
+
+/// What [`DockerSystem::follow_services`] yields: a line and the service whose
+/// container wrote it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceLine {
+    /// The `com.docker.compose.service` or `com.docker.swarm.service.name` label
+    /// of the container, or its name when it has neither.
+    pub service: String,
+    pub line: LogLine,
+}
+
+impl ServiceLine {
+    pub(crate) fn weigh(&self) -> usize {
+        self.line.weigh()
+    }
+}
+
+impl HostEvent {
+    pub(crate) fn weigh(&self) -> usize {
+        match self {
+            HostEvent::Log(line) => line.weigh(),
+            HostEvent::Lifecycle(_) => 0,
+        }
+    }
+}
+
+pub type EventStream = TaskStream<Result<DockerEvent, DockerError>>;
+
+impl DockerEvent {
+    pub fn id(&self) -> &str {
+        match self {
+            DockerEvent::Started { id, .. }
+            | DockerEvent::Stopped { id, .. }
+            | DockerEvent::Paused { id, .. }
+            | DockerEvent::Unpaused { id, .. } => id,
+        }
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        if value.get("Type")?.as_str()? != "container" {
+            return None;
+        }
+
+        let actor = value.get("Actor")?;
+        let id = actor.get("ID")?.as_str()?.to_owned();
+        let name = actor
+            .pointer("/Attributes/name")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_owned();
+
+        match value.get("Action")?.as_str()? {
+            "start" => Some(DockerEvent::Started { id, name }),
+            "die" => {
+                let at = value
+                    .get("timeNano")
+                    .and_then(|v| v.as_u64())
+                    .map(Duration::from_nanos)
+                    .or_else(|| value.get("time")?.as_u64().map(Duration::from_secs))
+                    .map(|since| UNIX_EPOCH + since);
+                Some(DockerEvent::Stopped { id, name, at })
+            }
+            "pause" => Some(DockerEvent::Paused { id, name }),
+            "unpause" => Some(DockerEvent::Unpaused { id, name }),
+            _ => None,
+        }
+    }
+}
+
+/// Subscribes to container start/die/pause/unpause events, narrowed down by `filters` (a json
+/// object in the daemon's `filters` format, e.g. `{"label": ["a=b"]}`).
+pub(crate) async fn events(
+    transport: &Transport,
+    filters: serde_json::Value,
+) -> Result<EventStream, DockerError> {
+    let mut all =
+        serde_json::json!({"type": ["container"], "event": ["start", "die", "pause", "unpause"]});
+    if let serde_json::Value::Object(filters) = filters {
+        for (key, value) in filters {
+            all[key] = value;
+        }
+    }
+
+    let mut response = transport
+        .get_ok(&format!("/events{}", filters_query(&all)))
+        .await?;
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let handle = tokio::spawn(async move {
+        let mut lines = LineSplitter::default();
+
+        while let Some(data) = response.data().await {
+            let data: Bytes = match data {
+                Ok(data) => data,
+                Err(e) => {
+                    let _ = tx.send(Err(e.into()));
+                    return;
+                }
+            };
+
+            for line in lines.push(&data) {
+                let event = match serde_json::from_str::<serde_json::Value>(&line) {
+                    Ok(value) => DockerEvent::from_json(&value).map(Ok),
+                    Err(e) => Some(Err(e.into())),
+                };
+
+                if let Some(event) = event {
+                    if tx.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(TaskStream::new(rx, handle))
+}
+
+impl DockerSystem {
+    /// Follows container start and stop events from the daemon.
+    pub async fn events(&self) -> Result<EventStream, DockerError> {
+        events(&self.transport, serde_json::json!({})).await
+    }
+}
@@ -0,0 +1,303 @@
+use bytes::{Buf, Bytes, BytesMut};
+
+/// Length of the header docker puts in front of every multiplexed frame.
+const HEADER_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamKind {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl StreamKind {
+    fn from_header(byte: u8) -> Self {
+        match byte {
+            0 => StreamKind::Stdin,
+            2 => StreamKind::Stderr,
+            _ => StreamKind::Stdout,
+        }
+    }
+}
+
+/// One multiplexed frame, exactly as the daemon framed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub stream: StreamKind,
+    pub payload: Bytes,
+}
+
+/// Splits the logs endpoint body back into the frames docker multiplexed it into.
+///
+/// Containers without a TTY get `[stream, 0, 0, 0, len_be32]` in front of every
+/// payload; TTY containers get the raw bytes, which are passed through as stdout.
+///
+/// [`FrameDecoder::push`] hands payloads out as soon as any of their bytes arrive,
+/// as slices of the chunks they came in, so a frame's payload may come back over
+/// several pieces. Only a partial header is ever buffered, however large the frame
+/// declares itself.
+#[derive(Debug)]
+pub struct FrameDecoder {
+    tty: bool,
+    header: [u8; HEADER_LEN],
+    header_len: usize,
+    /// The frame whose payload is being read, and how much of it is left.
+    current: Option<(StreamKind, usize)>,
+    /// What [`FrameDecoder::push_frames`] has of the current frame so far.
+    assembled: BytesMut,
+}
+
+impl FrameDecoder {
+    pub fn new(tty: bool) -> Self {
+        Self {
+            tty,
+            header: [0; HEADER_LEN],
+            header_len: 0,
+            current: None,
+            assembled: BytesMut::new(),
+        }
+    }
+
+    /// Feeds a chunk of the body and returns the payload pieces it contained.
+    pub fn push(&mut self, chunk: Bytes) -> Vec<(StreamKind, Bytes)> {
+        let mut pieces = Vec::new();
+        self.decode(chunk, |stream, piece, _| {
+            if !piece.is_empty() {
+                pieces.push((stream, piece));
+            }
+        });
+        pieces
+    }
+
+    /// Like [`FrameDecoder::push`], but only hands out whole frames, holding on to
+    /// a frame's payload until all of it has arrived. Empty frames are kept. TTY
+    /// output has no frames, so each chunk comes back as a stdout frame of its own.
+    ///
+    /// Use one decoder for either `push` or `push_frames`, not both.
+    pub fn push_frames(&mut self, chunk: Bytes) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut assembled = std::mem::take(&mut self.assembled);
+
+        self.decode(chunk, |stream, piece, complete| {
+            if !complete {
+                assembled.extend_from_slice(&piece);
+                return;
+            }
+
+            let payload = if assembled.is_empty() {
+                piece
+            } else {
+                assembled.extend_from_slice(&piece);
+                assembled.split().freeze()
+            };
+            frames.push(Frame { stream, payload });
+        });
+
+        self.assembled = assembled;
+        frames
+    }
+
+    /// How many newlines the payloads in `chunk` hold, following the frames as
+    /// [`FrameDecoder::push`] does without handing anything out.
+    pub(crate) fn count_lines(&mut self, chunk: Bytes) -> u64 {
+        let mut lines = 0;
+        self.decode(chunk, |_, piece, _| {
+            lines += piece.iter().filter(|&&byte| byte == b'\n').count() as u64;
+        });
+        lines
+    }
+
+    /// Walks `chunk`, calling `emit` with each payload piece and whether it ends
+    /// its frame.
+    fn decode(&mut self, mut chunk: Bytes, mut emit: impl FnMut(StreamKind, Bytes, bool)) {
+        if self.tty {
+            emit(StreamKind::Stdout, chunk, true);
+            return;
+        }
+
+        while !chunk.is_empty() {
+            match self.current {
+                Some((stream, remaining)) => {
+                    let n = remaining.min(chunk.len());
+                    emit(stream, chunk.split_to(n), n == remaining);
+                    self.current = Some((stream, remaining - n)).filter(|(_, left)| *left > 0);
+                }
+                None => {
+                    let n = (HEADER_LEN - self.header_len).min(chunk.len());
+                    self.header[self.header_len..self.header_len + n].copy_from_slice(&chunk[..n]);
+                    self.header_len += n;
+                    chunk.advance(n);
+
+                    if self.header_len == HEADER_LEN {
+                        let h = self.header;
+                        let stream = StreamKind::from_header(h[0]);
+                        let len = u32::from_be_bytes([h[4], h[5], h[6], h[7]]) as usize;
+                        self.header_len = 0;
+                        self.current = Some((stream, len)).filter(|(_, len)| *len > 0);
+                        if len == 0 {
+                            emit(stream, Bytes::new(), true);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Frame, FrameDecoder, StreamKind};
+    use crate::mock::frame;
+
+    /// Concatenates the pieces belonging to each stream.
+    fn collect(pieces: Vec<(StreamKind, Bytes)>) -> (Vec<u8>, Vec<u8>) {
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        for (stream, piece) in pieces {
+            match stream {
+                StreamKind::Stderr => stderr.extend_from_slice(&piece),
+                _ => stdout.extend_from_slice(&piece),
+            }
+        }
+        (stdout, stderr)
+    }
+
+    #[test]
+    fn frames_split_across_chunks() {
+        let mut body = frame(1, b"hello\n");
+        body.extend(frame(2, b"oops\n"));
+
+        let mut decoder = FrameDecoder::new(false);
+        let mut pieces = Vec::new();
+        for chunk in body.chunks(3) {
+            pieces.extend(decoder.push(Bytes::copy_from_slice(chunk)));
+        }
+
+        assert_eq!(collect(pieces), (b"hello\n".to_vec(), b"oops\n".to_vec()));
+    }
+
+    #[test]
+    fn large_frames_are_not_buffered() {
+        let payload = vec![b'x'; 5 * 1024 * 1024];
+        let body = frame(1, &payload);
+
+        let mut decoder = FrameDecoder::new(false);
+        let mut total = 0;
+        for chunk in body.chunks(64 * 1024) {
+            for (stream, piece) in decoder.push(Bytes::copy_from_slice(chunk)) {
+                assert_eq!(stream, StreamKind::Stdout);
+                assert!(piece.len() <= 64 * 1024);
+                total += piece.len();
+            }
+        }
+
+        assert_eq!(total, payload.len());
+    }
+
+    #[test]
+    fn push_frames_keeps_frame_boundaries() {
+        let mut body = frame(1, b"hello ");
+        body.extend(frame(1, b"world\n"));
+        body.extend(frame(2, b""));
+        body.extend(frame(2, b"oops\n"));
+
+        let mut decoder = FrameDecoder::new(false);
+        let mut frames = Vec::new();
+        for chunk in body.chunks(5) {
+            frames.extend(decoder.push_frames(Bytes::copy_from_slice(chunk)));
+        }
+
+        let frame = |stream, payload: &'static [u8]| Frame {
+            stream,
+            payload: Bytes::from_static(payload),
+        };
+        assert_eq!(
+            frames,
+            vec![
+                frame(StreamKind::Stdout, b"hello "),
+                frame(StreamKind::Stdout, b"world\n"),
+                frame(StreamKind::Stderr, b""),
+                frame(StreamKind::Stderr, b"oops\n"),
+            ]
+        );
+    }
+
+    /// A small xorshift generator, so the "random" test is the same every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn randomly_chunked_streams_reassemble() {
+        for seed in 1..=50u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+
+            let mut written = Vec::new();
+            let mut body = Vec::new();
+            for _ in 0..20 {
+                let (byte, stream) =
+                    [(1, StreamKind::Stdout), (2, StreamKind::Stderr)][rng.below(2)];
+                // Mostly small frames, some far bigger than any one chunk.
+                let len = match rng.below(4) {
+                    0 => rng.below(64 * 1024),
+                    _ => rng.below(300),
+                };
+                let payload = (0..len).map(|_| rng.below(256) as u8).collect::<Vec<_>>();
+                body.extend(frame(byte, &payload));
+                written.push(Frame {
+                    stream,
+                    payload: payload.into(),
+                });
+            }
+
+            let mut chunks = Vec::new();
+            let mut rest = &body[..];
+            while !rest.is_empty() {
+                let n = (1 + rng.below(512)).min(rest.len());
+                chunks.push(Bytes::copy_from_slice(&rest[..n]));
+                rest = &rest[n..];
+            }
+
+            let mut pieces = FrameDecoder::new(false);
+            let mut frames = FrameDecoder::new(false);
+            let (mut piece_list, mut frame_list) = (Vec::new(), Vec::new());
+            for chunk in chunks {
+                piece_list.extend(pieces.push(chunk.clone()));
+                frame_list.extend(frames.push_frames(chunk));
+            }
+
+            assert_eq!(frame_list, written, "seed {}", seed);
+            let expected = collect(
+                written
+                    .into_iter()
+                    .map(|frame| (frame.stream, frame.payload))
+                    .collect(),
+            );
+            assert_eq!(collect(piece_list), expected, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn lines_are_counted_in_payloads_only() {
+        // Ten bytes of payload put a newline byte in the header.
+        let mut body = frame(1, b"one\ntwo\nth");
+        body.extend(frame(2, b"ree\n"));
+        let body = Bytes::from(body);
+
+        let mut decoder = FrameDecoder::new(false);
+        let counted: u64 = (0..body.len())
+            .map(|i| decoder.count_lines(body.slice(i..i + 1)))
+            .sum();
+        assert_eq!(counted, 3);
+        // Without frames every newline byte counts, the header's too.
+        assert_eq!(FrameDecoder::new(true).count_lines(body), 4);
+    }
+}
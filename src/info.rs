@@ -0,0 +1,132 @@
+use serde_json::Value;
+
+use crate::{DockerError, DockerSystem};
+
+/// The parts of `/info` a status view usually needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineInfo {
+    /// Every container on the host, whatever its state.
+    pub containers: u64,
+    pub containers_running: u64,
+    pub containers_stopped: u64,
+    pub server_version: String,
+    pub operating_system: String,
+    pub storage_driver: String,
+}
+
+impl EngineInfo {
+    pub(crate) fn from_json(value: &Value) -> Result<Self, DockerError> {
+        let count = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_u64)
+                .ok_or_else(|| DockerError::InvalidResponse(format!("daemon info has no {}", key)))
+        };
+        let string = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned()
+        };
+
+        Ok(Self {
+            containers: count("Containers")?,
+            containers_running: count("ContainersRunning")?,
+            containers_stopped: count("ContainersStopped").unwrap_or_default(),
+            server_version: string("ServerVersion"),
+            operating_system: string("OperatingSystem"),
+            storage_driver: string("Driver"),
+        })
+    }
+}
+
+impl DockerSystem {
+    /// Checks the daemon is up and answering, the cheapest request there is.
+    /// Fails with [`DockerError::DaemonUnreachable`] if the socket can't be
+    /// connected to at all; a connection that breaks down afterwards is still a
+    /// [`DockerError::Transport`].
+    pub async fn ping(&self) -> Result<(), DockerError> {
+        match self.transport.get_ok("/_ping").await {
+            Ok(_) => Ok(()),
+            Err(DockerError::Transport(source)) if source.is_connect() => {
+                Err(DockerError::DaemonUnreachable {
+                    socket: self.transport.socket().to_owned(),
+                    source,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Host-wide counts and versions, as reported by `docker info`.
+    pub async fn info(&self) -> Result<EngineInfo, DockerError> {
+        EngineInfo::from_json(&self.transport.get_json("/info").await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mock, transport::Transport, DockerError, DockerSystem, EngineInfo};
+
+    #[tokio::test]
+    async fn info_reads_counts_and_versions() {
+        let daemon = mock::MockDaemon::start(|req| match req.uri().path() {
+            "/info" => mock::json(
+                200,
+                r#"{"Containers":5,"ContainersRunning":3,"ContainersPaused":0,"ContainersStopped":2,
+                    "ServerVersion":"24.0.7","OperatingSystem":"Ubuntu 22.04.3 LTS","Driver":"overlay2"}"#,
+            ),
+            _ => mock::json(404, r#"{"message":"page not found"}"#),
+        });
+        let system = DockerSystem::unrefreshed(daemon.transport());
+
+        assert_eq!(
+            system.info().await.unwrap(),
+            EngineInfo {
+                containers: 5,
+                containers_running: 3,
+                containers_stopped: 2,
+                server_version: "24.0.7".into(),
+                operating_system: "Ubuntu 22.04.3 LTS".into(),
+                storage_driver: "overlay2".into(),
+            }
+        );
+
+        let broken = mock::MockDaemon::start(|_| mock::json(200, r#"{"ServerVersion":"24.0.7"}"#));
+        assert!(matches!(
+            DockerSystem::unrefreshed(broken.transport()).info().await,
+            Err(DockerError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn ping_tells_a_missing_daemon_apart() {
+        let daemon = mock::MockDaemon::start(|req| match req.uri().path() {
+            "/_ping" => mock::json(200, "OK"),
+            _ => mock::json(404, r#"{"message":"page not found"}"#),
+        });
+        DockerSystem::unrefreshed(daemon.transport())
+            .ping()
+            .await
+            .unwrap();
+        assert_eq!(daemon.requests(), vec!["/_ping"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("docker.sock");
+        match DockerSystem::unrefreshed(Transport::new(&socket))
+            .ping()
+            .await
+        {
+            Err(DockerError::DaemonUnreachable { socket: at, .. }) => assert_eq!(at, socket),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Something answering, if not in HTTP, is there all the same.
+        let garbled = mock::RawDaemon::start(b"SSH-2.0-OpenSSH_9.6\r\n\r\n");
+        match DockerSystem::unrefreshed(garbled.transport()).ping().await {
+            Err(DockerError::Transport(e)) => assert!(!e.is_connect()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
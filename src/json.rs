@@ -0,0 +1,163 @@
+//! Just enough JSON to take a top-level array apart without holding all of it.
+
+use crate::DockerError;
+
+/// Where an [`ArraySplitter`] is in the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    Before,
+    Inside,
+    After,
+}
+
+/// Cuts a JSON array, fed in arbitrary chunks, into the raw bytes of its elements,
+/// so each can be parsed and dropped before the next one arrives. Only the element
+/// being read is ever buffered. Elements aren't validated beyond their nesting;
+/// that is left to whoever parses them.
+#[derive(Debug)]
+pub(crate) struct ArraySplitter {
+    position: Position,
+    /// Nesting inside the current element.
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    element: Vec<u8>,
+}
+
+impl Default for ArraySplitter {
+    fn default() -> Self {
+        Self {
+            position: Position::Before,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            element: Vec::new(),
+        }
+    }
+}
+
+impl ArraySplitter {
+    /// Feeds a chunk, returning the elements it completed.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Result<Vec<Vec<u8>>, DockerError> {
+        let mut elements = Vec::new();
+
+        for &byte in chunk {
+            match self.position {
+                Position::Before | Position::After if byte.is_ascii_whitespace() => {}
+                Position::Before if byte == b'[' => self.position = Position::Inside,
+                Position::Before => return Err(not_an_array()),
+                Position::After => {
+                    return Err(DockerError::InvalidResponse(
+                        "unexpected data after the array".into(),
+                    ))
+                }
+                Position::Inside => self.step(byte, &mut elements),
+            }
+        }
+
+        Ok(elements)
+    }
+
+    fn step(&mut self, byte: u8, elements: &mut Vec<Vec<u8>>) {
+        if self.in_string {
+            self.element.push(byte);
+            match byte {
+                _ if self.escaped => self.escaped = false,
+                b'\\' => self.escaped = true,
+                b'"' => self.in_string = false,
+                _ => {}
+            }
+            return;
+        }
+
+        match byte {
+            b',' | b']' if self.depth == 0 => {
+                if !self.element.is_empty() {
+                    elements.push(std::mem::take(&mut self.element));
+                }
+                if byte == b']' {
+                    self.position = Position::After;
+                }
+            }
+            _ if self.depth == 0 && byte.is_ascii_whitespace() => {}
+            b'"' => {
+                self.in_string = true;
+                self.element.push(byte);
+            }
+            b'{' | b'[' => {
+                self.depth += 1;
+                self.element.push(byte);
+            }
+            b'}' | b']' => {
+                self.depth = self.depth.saturating_sub(1);
+                self.element.push(byte);
+            }
+            _ => self.element.push(byte),
+        }
+    }
+
+    /// Checks the array was closed once the body has ended.
+    pub(crate) fn finish(&self) -> Result<(), DockerError> {
+        match self.position {
+            Position::After => Ok(()),
+            Position::Before => Err(not_an_array()),
+            Position::Inside => Err(DockerError::InvalidResponse("array ended early".into())),
+        }
+    }
+}
+
+fn not_an_array() -> DockerError {
+    DockerError::InvalidResponse("expected a json array".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArraySplitter;
+    use crate::DockerError;
+
+    #[test]
+    fn elements_split_across_chunks() {
+        let body = br#" [ {"a":"x,]}\"y"} , [1,[2]] ,3 ,"s"] "#;
+
+        for size in [1, 2, 5, body.len()] {
+            let mut splitter = ArraySplitter::default();
+            let mut elements = Vec::new();
+            for chunk in body.chunks(size) {
+                elements.extend(splitter.push(chunk).unwrap());
+            }
+            splitter.finish().unwrap();
+
+            let elements = elements
+                .iter()
+                .map(|e| std::str::from_utf8(e).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                elements,
+                vec![r#"{"a":"x,]}\"y"}"#, "[1,[2]]", "3", r#""s""#]
+            );
+        }
+    }
+
+    #[test]
+    fn only_whole_arrays_are_accepted() {
+        let mut empty = ArraySplitter::default();
+        assert_eq!(empty.push(b"[ ]\n").unwrap(), Vec::<Vec<u8>>::new());
+        empty.finish().unwrap();
+
+        let mut object = ArraySplitter::default();
+        assert!(matches!(
+            object.push(br#"{"message":"oops"}"#),
+            Err(DockerError::InvalidResponse(_))
+        ));
+
+        let mut truncated = ArraySplitter::default();
+        truncated.push(br#"[{"Id":"a"},{"Id""#).unwrap();
+        assert!(matches!(
+            truncated.finish(),
+            Err(DockerError::InvalidResponse(_))
+        ));
+
+        let mut trailing = ArraySplitter::default();
+        assert!(trailing.push(b"[1] 2").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use serde_json::Value;
+
+/// How severe a structured log line says it is, least severe first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl LogLevel {
+    /// Reads a level the way the usual loggers write one: a name in any case
+    /// (`"warn"`, `"WARNING"`, `"err"`, `"critical"`, ...) or a pino/bunyan style
+    /// number from 10 (trace) to 60 (fatal).
+    pub fn parse(value: &Value) -> Option<Self> {
+        if let Some(number) = value.as_u64() {
+            return match number {
+                0..=10 => Some(LogLevel::Trace),
+                11..=20 => Some(LogLevel::Debug),
+                21..=30 => Some(LogLevel::Info),
+                31..=40 => Some(LogLevel::Warn),
+                41..=50 => Some(LogLevel::Error),
+                _ => Some(LogLevel::Fatal),
+            };
+        }
+
+        let level = match value.as_str()?.to_ascii_lowercase().as_str() {
+            "trace" => LogLevel::Trace,
+            "debug" => LogLevel::Debug,
+            "info" | "information" | "notice" => LogLevel::Info,
+            "warn" | "warning" => LogLevel::Warn,
+            "error" | "err" => LogLevel::Error,
+            "fatal" | "critical" | "crit" | "panic" | "emergency" | "alert" => LogLevel::Fatal,
+            _ => return None,
+        };
+        Some(level)
+    }
+
+    /// The `level` of a line that is a JSON object, if it has one this can read.
+    pub(crate) fn of(message: &str) -> Option<Self> {
+        if !message.trim_start().starts_with('{') {
+            return None;
+        }
+
+        let value = serde_json::from_str::<Value>(message).ok()?;
+        Self::parse(value.get("level")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogLevel;
+
+    #[test]
+    fn levels_are_read_from_json_lines() {
+        assert_eq!(
+            LogLevel::of(r#"{"level":"WARNING","msg":"disk 91% full"}"#),
+            Some(LogLevel::Warn)
+        );
+        assert_eq!(
+            LogLevel::of(r#"  {"level":50,"msg":"boom"}"#),
+            Some(LogLevel::Error)
+        );
+        assert_eq!(LogLevel::of(r#"{"level":"chatty"}"#), None);
+        assert_eq!(LogLevel::of(r#"{"msg":"no level"}"#), None);
+        assert_eq!(LogLevel::of("level=error plain text"), None);
+        assert_eq!(LogLevel::of(r#"{"level":"error""#), None);
+
+        assert!(LogLevel::Warn < LogLevel::Error);
+    }
+}
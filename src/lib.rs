@@ -1,166 +1,314 @@
-use std::{
-    collections::{HashMap, HashSet},
-    error::Error,
-    time::{SystemTime, UNIX_EPOCH},
-};
-
-use hyper::{
-    body::{Bytes, HttpBody},
-    Client,
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use futures_lite::Stream;
+use hyper::body::{Bytes, HttpBody};
+
+mod ansi;
+mod budget;
+mod changes;
+mod cluster;
+mod connector;
+mod container;
+mod container_log;
+mod context;
+mod error;
+mod events;
+mod frame;
+mod info;
+mod json;
+mod level;
+mod line;
+mod metrics;
+#[cfg(test)]
+mod mock;
+mod stream;
+mod supervisor;
+mod timestamp;
+mod tls;
+mod transport;
+
+pub use changes::{ChangeKind, FsChange};
+pub use cluster::{DockerCluster, Tagged};
+pub use container::{
+    ContainerDelta, ContainerInfo, ContainerListDecoder, ContainerState, PortMapping,
 };
-use hyperlocal::{UnixClientExt, UnixConnector};
-use once_cell::sync::Lazy;
-use tokio::{sync::mpsc::UnboundedReceiver, task::JoinHandle, try_join};
-
-static UNIX_CLIENT: Lazy<Client<UnixConnector>> = Lazy::new(|| Client::unix());
-static CONTAINERS_ENDPOINT: Lazy<hyper::Uri> =
-    Lazy::new(|| hyperlocal::Uri::new("/var/run/docker.sock", "/containers/json").into());
+pub use container_log::{ContainerLog, ContainerLogOptions, Sequenced, Since};
+pub use error::DockerError;
+pub use events::{DockerEvent, EventStream, FollowEvent, HostEvent, ServiceLine};
+pub use frame::{Frame, FrameDecoder, StreamKind};
+pub use info::EngineInfo;
+pub use level::LogLevel;
+pub use line::LogLine;
+pub use stream::{LogStream, TaskStream};
+pub use supervisor::AttachOptions;
+pub use tls::TlsConfig;
+pub use tokio_util::sync::CancellationToken;
+use transport::Transport;
+pub use transport::{DockerConfig, TcpEndpoint, DEFAULT_DOCKER_SOCKET};
 
 #[derive(Debug)]
 pub struct DockerSystem {
+    transport: Transport,
     running_containers: HashSet<[u8; 12]>,
     container_logs: HashMap<[u8; 12], ContainerLog>,
+    /// What the last refresh listed, so stopped containers can still be described.
+    container_info: HashMap<[u8; 12], ContainerInfo>,
+    /// The latest non-empty refreshes, oldest first, at most `retained_deltas`.
+    recent_deltas: VecDeque<ContainerDelta>,
+    retained_deltas: usize,
 }
 
-#[derive(Debug)]
-pub struct ContainerLog {
-    pub id: String,
-    pub handle: JoinHandle<()>,
-    pub stdout: UnboundedReceiver<Bytes>,
-    pub stderr: UnboundedReceiver<Bytes>,
-}
-
-impl ContainerLog {
-    pub async fn new(id: String) -> Result<Self, Box<dyn Error>> {
-        let start = SystemTime::now();
-        let now = start
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
-
-        let stdout_uri = hyperlocal::Uri::new(
-            "/var/run/docker.sock",
-            &format!("/containers/{}/logs?stdout=1&follow=1", &id),
-        )
-        .into();
-
-        let mut stdout_response = UNIX_CLIENT.get(stdout_uri).await?;
-
-        let (stdout_tx, stdout_rx) = tokio::sync::mpsc::unbounded_channel::<Bytes>();
-
-        let stderr_uri = hyperlocal::Uri::new(
-            "/var/run/docker.sock",
-            &format!("/containers/{}/logs?stderr=1&follow=1&since={}", &id, now),
-        )
-        .into();
-
-        let mut stderr_response = UNIX_CLIENT.get(stderr_uri).await?;
-        let (stderr_tx, stderr_rx) = tokio::sync::mpsc::unbounded_channel::<Bytes>();
-
-        let handle = tokio::spawn(async move {
-            try_join!(
-                tokio::spawn(async move {
-                    while let Some(data) = stdout_response.data().await {
-                        stdout_tx.send(data.unwrap()).unwrap();
-                    }
-                }),
-                tokio::spawn(async move {
-                    while let Some(data) = stderr_response.data().await {
-                        stderr_tx.send(data.unwrap()).unwrap();
-                    }
-                })
-            )
-            .unwrap();
-        });
-
-        Ok(Self {
-            id,
-            handle,
-            stdout: stdout_rx,
-            stderr: stderr_rx,
-        })
-    }
+fn container_id(id: &[u8; 12]) -> Result<String, DockerError> {
+    std::str::from_utf8(id)
+        .ok()
+        .and_then(|s| hex::decode(s).ok())
+        .map(hex::encode)
+        .ok_or_else(|| DockerError::InvalidResponse(format!("container id {:?} is not hex", id)))
 }
 
 impl DockerSystem {
-    pub async fn refresh_containers(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut response = UNIX_CLIENT.get(CONTAINERS_ENDPOINT.clone()).await.unwrap();
-        let mut buf: Vec<u8> = Vec::with_capacity(
-            (response
-                .size_hint()
-                .upper()
-                .unwrap_or_else(|| response.size_hint().lower())) as usize,
-        );
-
-        while let Some(data) = response.data().await {
-            let data = data?;
-            buf.extend_from_slice(&data);
-        }
-
-        let parsed: serde_json::Value = serde_json::from_slice(&buf)?;
-
-        let currently_running = parsed
-            .as_array()
-            .unwrap()
+    /// Re-lists the running containers, attaching to those that started since the
+    /// last refresh and dropping those that stopped, and reports which they were.
+    pub async fn refresh_containers(&mut self) -> Result<ContainerDelta, DockerError> {
+        let listed = container::list(&self.transport, "")
+            .await?
             .into_iter()
-            .map(|v| {
-                let bytes = &v.get("Id").unwrap().as_str().unwrap().as_bytes()[0..12];
+            .map(|info| {
+                let bytes = info.id.as_bytes().get(0..12).ok_or_else(|| {
+                    DockerError::InvalidResponse(format!("container id {:?} is too short", info.id))
+                })?;
                 let mut arr = [0u8; 12];
                 arr.clone_from_slice(bytes);
-                arr
+                Ok((arr, info))
             })
-            .collect::<HashSet<_>>();
+            .collect::<Result<HashMap<_, _>, DockerError>>()?;
+        let currently_running = listed.keys().copied().collect::<HashSet<_>>();
 
-        let new = currently_running
+        // Both sides come from the same two sets, before either is touched.
+        let started = currently_running
             .difference(&self.running_containers)
-            .map(|f| f.clone())
+            .copied()
+            .collect::<Vec<_>>();
+        let stopped = self
+            .running_containers
+            .difference(&currently_running)
+            .copied()
             .collect::<Vec<_>>();
 
-        for id in &new {
-            let str_id = hex::encode(hex::decode(std::str::from_utf8(id)?)?);
-            self.container_logs
-                .insert(*id, ContainerLog::new(str_id).await?);
+        let mut delta = ContainerDelta::default();
+
+        for id in &started {
+            let str_id = container_id(id)?;
+            self.container_logs.insert(
+                *id,
+                ContainerLog::open(&self.transport, str_id, &Default::default()).await?,
+            );
+            delta.started.push(listed[id].clone());
         }
 
-        self.running_containers.extend(new);
+        for id in &stopped {
+            self.container_logs.remove(id);
+            delta.stopped.extend(self.container_info.remove(id));
+        }
 
-        let dropped = self
-            .running_containers
-            .difference(&currently_running)
-            .map(|f| f.clone())
-            .collect::<Vec<_>>();
+        self.running_containers = currently_running;
+        self.container_info = listed;
+        self.retain(&delta);
 
-        for drop in dropped {
-            self.running_containers.remove(&drop);
-            self.container_logs.remove(&drop);
+        Ok(delta)
+    }
+
+    /// [`DockerSystem::refresh_containers`], given up as soon as `token` is
+    /// cancelled with [`DockerError::Cancelled`], so a shutdown doesn't have to wait
+    /// out a hung listing. A cancelled refresh reports nothing; the next one picks
+    /// up every change since the last that completed.
+    pub async fn refresh_containers_with_cancel(
+        &mut self,
+        token: CancellationToken,
+    ) -> Result<ContainerDelta, DockerError> {
+        tokio::select! {
+            biased;
+            _ = token.cancelled() => Err(DockerError::Cancelled),
+            delta = self.refresh_containers() => delta,
         }
+    }
 
-        Ok(())
+    fn retain(&mut self, delta: &ContainerDelta) {
+        if self.retained_deltas == 0 || delta == &ContainerDelta::default() {
+            return;
+        }
+        if self.recent_deltas.len() == self.retained_deltas {
+            self.recent_deltas.pop_front();
+        }
+        self.recent_deltas.push_back(delta.clone());
+    }
+
+    /// What the latest refreshes that saw a change reported, oldest first, so a
+    /// late subscriber can catch up on recent starts and stops without re-listing.
+    /// How many are kept is set by [`DockerConfig::retained_deltas`].
+    pub fn recent_deltas(&self) -> impl Iterator<Item = &ContainerDelta> {
+        self.recent_deltas.iter()
     }
 
     pub fn running_containers(&self) -> Vec<String> {
         self.running_containers
             .iter()
-            .map(|c| hex::encode(hex::decode(std::str::from_utf8(c).unwrap()).unwrap()))
+            .filter_map(|c| container_id(c).ok())
             .collect::<Vec<_>>()
     }
 
-    pub async fn new() -> Result<Self, Box<dyn Error>> {
-        let mut s = Self {
-            running_containers: Default::default(),
-            container_logs: Default::default(),
-        };
+    /// Any one of the running containers, or `None` on an idle host.
+    pub fn first_container(&self) -> Option<String> {
+        self.running_containers
+            .iter()
+            .find_map(|c| container_id(c).ok())
+    }
+
+    /// Makes a GET to `path_and_query` (e.g. `/containers/abc/top?ps_args=aux`)
+    /// on the system's socket and streams the response body as it arrives, for
+    /// endpoints the crate doesn't model yet. Nothing is interpreted at this
+    /// level: the body comes through whatever the status, so checking it is the
+    /// caller's job, as is decoding multiplexed or JSON bodies. The request is
+    /// made once the stream is first polled.
+    pub fn get_stream(
+        &self,
+        path_and_query: &str,
+    ) -> impl Stream<Item = Result<Bytes, DockerError>> + Send + 'static {
+        enum State {
+            Request(Transport, String),
+            Body(hyper::Body),
+            Done,
+        }
+
+        let start = State::Request(self.transport.clone(), path_and_query.to_owned());
+        futures_lite::stream::unfold(start, |state| async move {
+            let mut body = match state {
+                State::Request(transport, path) => match transport.get(&path).await {
+                    Ok(response) => response.into_body(),
+                    Err(e) => return Some((Err(e), State::Done)),
+                },
+                State::Body(body) => body,
+                State::Done => return None,
+            };
+
+            match body.data().await? {
+                Ok(chunk) => Some((Ok(chunk), State::Body(body))),
+                Err(e) => Some((Err(e.into()), State::Done)),
+            }
+        })
+    }
 
-        s.refresh_containers().await.unwrap();
+    pub async fn new() -> Result<Self, DockerError> {
+        Self::with_config(Default::default()).await
+    }
+
+    /// Connects to `config.socket`, sending every request with `config`. Everything
+    /// the system opens later, log followers included, goes to that socket too.
+    pub async fn with_config(config: DockerConfig) -> Result<Self, DockerError> {
+        let transport = Transport::configured(&config.socket, &config)?;
+
+        let mut s = Self::unrefreshed(transport);
+        s.retained_deltas = config.retained_deltas;
+
+        if config.ping_on_connect {
+            s.ping().await?;
+        }
+        s.refresh_containers().await?;
 
         Ok(s)
     }
+
+    #[cfg(test)]
+    pub(crate) async fn with_transport(transport: Transport) -> Result<Self, DockerError> {
+        let mut s = Self::unrefreshed(transport);
+
+        s.refresh_containers().await?;
+
+        Ok(s)
+    }
+
+    /// A system that hasn't listed (and so hasn't attached to) anything yet.
+    pub(crate) fn unrefreshed(transport: Transport) -> Self {
+        Self {
+            transport,
+            running_containers: Default::default(),
+            container_logs: Default::default(),
+            container_info: Default::default(),
+            recent_deltas: Default::default(),
+            retained_deltas: DockerConfig::default().retained_deltas,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::DockerSystem;
+    use std::sync::{Arc, Mutex};
+
+    use bytes::Bytes;
+    use futures_lite::StreamExt;
+    use hyper::{Body, Response};
+
+    use crate::{
+        mock, CancellationToken, ContainerDelta, ContainerInfo, ContainerLog, ContainerLogOptions,
+        DockerCluster, DockerConfig, DockerError, DockerEvent, DockerSystem, EngineInfo,
+        EventStream, FollowEvent, Frame, FrameDecoder, HostEvent, LogLine, LogStream, Sequenced,
+        ServiceLine, TaskStream, Transport,
+    };
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    /// Everything public can be moved into and shared between web handlers.
+    #[test]
+    fn public_types_are_send_and_sync() {
+        assert_send_sync::<DockerSystem>();
+        assert_send_sync::<DockerCluster>();
+        assert_send_sync::<ContainerLog<Bytes>>();
+        assert_send_sync::<ContainerLog<LogLine>>();
+        assert_send_sync::<ContainerLog<Frame>>();
+        assert_send_sync::<ContainerLog<Sequenced>>();
+        assert_send_sync::<ContainerLogOptions>();
+        assert_send_sync::<LogStream>();
+        assert_send_sync::<TaskStream<LogLine>>();
+        assert_send_sync::<TaskStream<HostEvent>>();
+        assert_send_sync::<TaskStream<FollowEvent>>();
+        assert_send_sync::<TaskStream<ServiceLine>>();
+        assert_send_sync::<EventStream>();
+        assert_send_sync::<DockerEvent>();
+        assert_send_sync::<DockerError>();
+        assert_send_sync::<ContainerInfo>();
+        assert_send_sync::<EngineInfo>();
+        assert_send_sync::<FrameDecoder>();
+    }
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    /// Handlers await these, so their futures have to be `Send` as well.
+    #[test]
+    fn public_futures_are_send() {
+        let system = DockerSystem::unrefreshed(Transport::default());
+        let cluster = DockerCluster::new(Vec::new());
+        let mut refreshing = DockerSystem::unrefreshed(Transport::default());
+
+        assert_send(&DockerSystem::new());
+        assert_send(&DockerSystem::with_config(Default::default()));
+        assert_send(&refreshing.refresh_containers());
+        assert_send(&system.containers());
+        assert_send(&system.containers_by_created());
+        assert_send(&system.is_running("abc"));
+        assert_send(&system.info());
+        assert_send(&system.get_stream("/_ping"));
+        assert_send(&system.events());
+        assert_send(&system.attach_all());
+        assert_send(&system.host_events());
+        assert_send(&system.follow_named("web"));
+        assert_send(&cluster.containers());
+        assert_send(&cluster.attach_all());
+        assert_send(&ContainerLog::new("abc".into()));
+        assert_send(&ContainerLog::lines("abc".into()));
+        assert_send(&ContainerLog::frames("abc".into()));
+        assert_send(&ContainerLog::sequenced("abc".into()));
+    }
 
     #[tokio::test]
     async fn list_containers_test() {
@@ -173,10 +321,340 @@ mod tests {
     async fn socket_open() {
         let system = DockerSystem::new().await.unwrap();
 
-        for (key, mut value) in system.container_logs {
-            let msg = value.stdout.recv().await.unwrap();
-            dbg!(msg);
-            break;
+        match system.first_container() {
+            Some(id) => {
+                let mut log = ContainerLog::new(id).await.unwrap();
+                let msg = log.stdout.recv().await;
+                dbg!(msg);
+            }
+            None => println!("no running containers"),
         }
     }
+
+    #[tokio::test]
+    async fn raw_bodies_stream_whatever_the_status() {
+        let daemon = mock::MockDaemon::start(|req| match req.uri().path() {
+            "/containers/abc/top" => {
+                let (mut tx, body) = Body::channel();
+                tx.try_send_data(Bytes::from_static(b"{\"Titles\":"))
+                    .unwrap();
+                tokio::spawn(async move {
+                    tx.send_data(Bytes::from_static(b"[\"PID\"]}"))
+                        .await
+                        .unwrap();
+                });
+                Response::new(body)
+            }
+            _ => mock::json(404, r#"{"message":"page not found"}"#),
+        });
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let collect = |path: &'static str| {
+            let chunks = system.get_stream(path);
+            async move {
+                let chunks = chunks.collect::<Vec<_>>().await;
+                chunks
+                    .into_iter()
+                    .map(Result::unwrap)
+                    .collect::<Vec<_>>()
+                    .concat()
+            }
+        };
+
+        assert_eq!(
+            collect("/containers/abc/top?ps_args=aux").await,
+            br#"{"Titles":["PID"]}"#
+        );
+        assert_eq!(collect("/nope").await, br#"{"message":"page not found"}"#);
+        assert_eq!(daemon.requests()[0], "/containers/abc/top?ps_args=aux");
+
+        let unreachable = DockerSystem::unrefreshed(Transport::new("/nonexistent/docker.sock"));
+        let mut chunks = Box::pin(unreachable.get_stream("/_ping"));
+        assert!(matches!(
+            chunks.next().await,
+            Some(Err(DockerError::Transport(_)))
+        ));
+        assert!(chunks.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn custom_socket_is_used_for_everything() {
+        let id = "c".repeat(64);
+        let listing = format!(r#"[{{"Id":"{}","Names":["/web"]}}]"#, id);
+        let daemon = mock::MockDaemon::start(move |req| match req.uri().path() {
+            "/containers/json" => mock::json(200, &listing),
+            path if path.ends_with("/json") => mock::json(200, r#"{"Config":{"Tty":false}}"#),
+            _ => Response::new(Body::empty()),
+        });
+
+        // Fails outright if anything went to a default socket with no daemon
+        // behind it, and otherwise the requests below wouldn't all be here.
+        let config = DockerConfig {
+            socket: daemon.socket.clone(),
+            ..Default::default()
+        };
+        let system = DockerSystem::with_config(config).await.unwrap();
+        assert_eq!(system.running_containers(), vec![&id[..12]]);
+
+        let mut requests = daemon
+            .requests()
+            .into_iter()
+            .map(|r| r.split('&').next().unwrap_or_default().to_owned())
+            .collect::<Vec<_>>();
+        requests.sort();
+        assert_eq!(
+            requests,
+            vec![
+                "/_ping".to_owned(),
+                format!("/containers/{}/json", &id[..12]),
+                format!("/containers/{}/logs?stderr=1", &id[..12]),
+                format!("/containers/{}/logs?stdout=1", &id[..12]),
+                "/containers/json".to_owned(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn recent_deltas_keep_the_latest_changes() {
+        let listing: Arc<Mutex<String>> = Default::default();
+        let current = listing.clone();
+        let daemon = mock::MockDaemon::start(move |req| {
+            if req.uri().path() == "/containers/json" {
+                mock::json(200, &current.lock().unwrap())
+            } else if req.uri().path().ends_with("/json") {
+                mock::json(200, r#"{"Config":{"Tty":false}}"#)
+            } else {
+                Response::new(Body::empty())
+            }
+        });
+        let mut system = DockerSystem::unrefreshed(daemon.transport());
+        system.retained_deltas = 2;
+
+        let ids = |deltas: Vec<&ContainerDelta>| {
+            deltas
+                .into_iter()
+                .map(|d| {
+                    let ids =
+                        |c: &[ContainerInfo]| c.iter().map(|c| c.id[..1].to_owned()).collect();
+                    (ids(&d.started), ids(&d.stopped))
+                })
+                .collect::<Vec<(Vec<String>, Vec<String>)>>()
+        };
+        for running in ["a", "a", "b", ""] {
+            *listing.lock().unwrap() = match running {
+                "" => "[]".to_owned(),
+                id => format!(r#"[{{"Id":"{}"}}]"#, id.repeat(64)),
+            };
+            system.refresh_containers().await.unwrap();
+        }
+
+        // The first start fell out, and the refresh that changed nothing never counted.
+        let s = |id: &str| vec![id.to_owned()];
+        assert_eq!(
+            ids(system.recent_deltas().collect()),
+            vec![(s("b"), s("a")), (vec![], s("b"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn hung_refresh_can_be_cancelled() {
+        let hung: Arc<Mutex<Vec<hyper::body::Sender>>> = Default::default();
+        let held = hung.clone();
+        let daemon = mock::MockDaemon::start(move |_| {
+            let (tx, body) = Body::channel();
+            held.lock().unwrap().push(tx);
+            Response::new(body)
+        });
+        let mut system = DockerSystem::unrefreshed(daemon.transport());
+
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            cancel.cancel();
+        });
+        assert!(matches!(
+            system.refresh_containers_with_cancel(token).await,
+            Err(DockerError::Cancelled)
+        ));
+        assert_eq!(hung.lock().unwrap().len(), 1);
+
+        let id = "a".repeat(64);
+        let listing = format!(r#"[{{"Id":"{}"}}]"#, id);
+        let answering = mock::MockDaemon::start(move |req| match req.uri().path() {
+            "/containers/json" => mock::json(200, &listing),
+            path if path.ends_with("/json") => mock::json(200, r#"{"Config":{"Tty":false}}"#),
+            _ => Response::new(Body::empty()),
+        });
+        let mut system = DockerSystem::unrefreshed(answering.transport());
+        let delta = system
+            .refresh_containers_with_cancel(CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(delta.started[0].id, id);
+    }
+
+    #[tokio::test]
+    async fn refresh_deltas_follow_churn() {
+        let listing: Arc<Mutex<String>> = Default::default();
+        let current = listing.clone();
+        let daemon = mock::MockDaemon::start(move |req| {
+            if req.uri().path() == "/containers/json" {
+                mock::json(200, &current.lock().unwrap())
+            } else if req.uri().path().ends_with("/json") {
+                mock::json(200, r#"{"Config":{"Tty":false}}"#)
+            } else {
+                Response::new(Body::empty())
+            }
+        });
+        let mut system = DockerSystem::unrefreshed(daemon.transport());
+
+        let sorted = |containers: &[ContainerInfo]| {
+            let mut ids = containers
+                .iter()
+                .map(|c| c.id[..1].to_owned())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        // Running containers at each refresh, then what should have started and stopped.
+        let steps: &[(&str, &str, &str)] = &[
+            ("a", "a", ""),
+            ("ab", "b", ""),
+            ("ab", "", ""),
+            ("bc", "c", "a"),
+            ("", "", "bc"),
+            ("", "", ""),
+            ("abc", "abc", ""),
+            ("ade", "de", "bc"),
+        ];
+        for &(running, started, stopped) in steps {
+            let listed = running
+                .chars()
+                .map(|c| format!(r#"{{"Id":"{}"}}"#, c.to_string().repeat(64)))
+                .collect::<Vec<_>>();
+            *listing.lock().unwrap() = format!("[{}]", listed.join(","));
+
+            let delta = system.refresh_containers().await.unwrap();
+            let chars = |ids: &str| ids.chars().map(String::from).collect::<Vec<_>>();
+            assert_eq!(
+                sorted(&delta.started),
+                chars(started),
+                "starts at {:?}",
+                running
+            );
+            assert_eq!(
+                sorted(&delta.stopped),
+                chars(stopped),
+                "stops at {:?}",
+                running
+            );
+
+            let mut now = system
+                .running_containers()
+                .into_iter()
+                .map(|id| id[..1].to_owned())
+                .collect::<Vec<_>>();
+            now.sort();
+            assert_eq!(now, chars(running));
+            assert_eq!(system.container_logs.len(), running.len());
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_reports_started_and_stopped_with_info() {
+        let (a, b) = ("a".repeat(64), "b".repeat(64));
+        let listing: Arc<Mutex<String>> = Default::default();
+        *listing.lock().unwrap() =
+            format!(r#"[{{"Id":"{}","Names":["/web"],"Image":"nginx"}}]"#, a);
+
+        let current = listing.clone();
+        let daemon = mock::MockDaemon::start(move |req| {
+            if req.uri().path() == "/containers/json" {
+                mock::json(200, &current.lock().unwrap())
+            } else if req.uri().path().ends_with("/json") {
+                mock::json(200, r#"{"Config":{"Tty":false}}"#)
+            } else {
+                Response::new(Body::empty())
+            }
+        });
+        let mut system = DockerSystem::unrefreshed(daemon.transport());
+
+        let delta = system.refresh_containers().await.unwrap();
+        assert_eq!(delta.stopped, vec![]);
+        match &delta.started[..] {
+            [web] => assert_eq!(
+                (&web.id, &web.names[0], &web.image[..]),
+                (&a, &"/web".to_owned(), "nginx")
+            ),
+            started => panic!("unexpected starts: {:?}", started),
+        }
+        let requests = daemon.requests().len();
+
+        *listing.lock().unwrap() = format!(r#"[{{"Id":"{}","Names":["/db"]}}]"#, b);
+        let delta = system.refresh_containers().await.unwrap();
+        assert_eq!(
+            delta.started.iter().map(|c| &c.id).collect::<Vec<_>>(),
+            vec![&b]
+        );
+        assert_eq!(
+            delta.stopped.iter().map(|c| &c.id).collect::<Vec<_>>(),
+            vec![&a]
+        );
+
+        // Only the listing, the new container's TTY check and its logs; its
+        // summary comes from the listing rather than another inspect.
+        let requests = &daemon.requests()[requests..];
+        assert_eq!(requests.len(), 4, "{:?}", requests);
+        let inspects = requests
+            .iter()
+            .filter(|r| r.ends_with("/json") && *r != "/containers/json")
+            .collect::<Vec<_>>();
+        assert_eq!(inspects, vec![&format!("/containers/{}/json", &b[..12])]);
+    }
+
+    #[tokio::test]
+    async fn tty_containers_are_followed_once() {
+        let id = "7".repeat(64);
+        let listing = format!(r#"[{{"Id":"{}"}}]"#, id);
+        let daemon = mock::MockDaemon::start(move |req| match req.uri().path() {
+            "/containers/json" => mock::json(200, &listing),
+            path if path.ends_with("/json") => mock::json(200, r#"{"Config":{"Tty":true}}"#),
+            _ => Response::new(Body::from("$ hello\r\n")),
+        });
+
+        let mut system = DockerSystem::with_transport(daemon.transport())
+            .await
+            .unwrap();
+        let log = system.container_logs.values_mut().next().unwrap();
+        let mut received = Vec::new();
+        while let Some(chunk) = log.stdout.recv().await {
+            received.extend_from_slice(&chunk);
+        }
+        assert_eq!(received, b"$ hello\r\n");
+        assert!(log.stderr.recv().await.is_none());
+
+        let follows = daemon
+            .requests()
+            .into_iter()
+            .filter(|r| r.contains("/logs"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            follows,
+            vec![format!(
+                "/containers/{}/logs?stdout=1&stderr=1&follow=1",
+                &id[..12]
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn first_container_on_idle_host() {
+        let daemon = mock::MockDaemon::start(|_| mock::json(200, "[]"));
+        let system = DockerSystem::with_transport(daemon.transport())
+            .await
+            .unwrap();
+
+        assert!(system.running_containers().is_empty());
+        assert_eq!(system.first_container(), None);
+    }
 }
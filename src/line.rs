@@ -0,0 +1,577 @@
+use std::time::SystemTime;
+
+use bytes::Bytes;
+
+use crate::{
+    ansi::AnsiStripper,
+    frame::{FrameDecoder, StreamKind},
+    level::LogLevel,
+    timestamp,
+};
+
+/// One line of a container's output, without its trailing newline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub container_id: String,
+    pub stream: StreamKind,
+    pub message: String,
+    /// When the daemon logged the line, in [`timestamps`] mode. `None` for a line
+    /// that came without a timestamp even so.
+    ///
+    /// [`timestamps`]: crate::ContainerLogOptions::timestamps
+    pub timestamp: Option<SystemTime>,
+    /// The stream ended, or was [stopped](crate::ContainerLog::stop), before this
+    /// line's newline arrived, so it may be missing its end.
+    pub incomplete: bool,
+}
+
+impl LogLine {
+    /// The 12 character id `docker ps` shows, for compact display.
+    pub fn short_id(&self) -> &str {
+        self.container_id.get(..12).unwrap_or(&self.container_id)
+    }
+
+    /// The line as a Server-Sent Events message, ready to write to an `EventSource`
+    /// response: `data: {json}` and the blank line ending the event.
+    ///
+    /// The JSON has `container_id`, `stream` (`"stdout"`, `"stderr"` or `"stdin"`),
+    /// `message` and `timestamp`, an RFC 3339 string or `null`.
+    pub fn to_sse(&self) -> String {
+        let stream = match self.stream {
+            StreamKind::Stdin => "stdin",
+            StreamKind::Stdout => "stdout",
+            StreamKind::Stderr => "stderr",
+        };
+        let json = serde_json::json!({
+            "container_id": self.container_id,
+            "stream": stream,
+            "message": self.message,
+            "timestamp": self.timestamp.map(timestamp::format),
+        });
+        sse_event(&json.to_string())
+    }
+
+    /// What the line counts for against a byte budget.
+    pub(crate) fn weigh(&self) -> usize {
+        self.message.len()
+    }
+}
+
+/// Frames `data` as one SSE event. Each of its lines gets a `data:` field of its
+/// own, since a line break inside a field would end it, and `\r` counts as one.
+fn sse_event(data: &str) -> String {
+    let mut event = String::with_capacity(data.len() + 8);
+    for line in data.split("\r\n").flat_map(|l| l.split(['\r', '\n'])) {
+        event.push_str("data: ");
+        event.push_str(line);
+        event.push('\n');
+    }
+    event.push('\n');
+    event
+}
+
+/// Buffers bytes until a full `\n` terminated line is available, dropping the `\r`
+/// of a `\r\n` ending too. With a `max_len` longer lines are handed out in pieces
+/// of at most that many bytes instead of being buffered whole. Pieces are cut
+/// between characters, so one only runs over `max_len` when a single character
+/// is longer than that.
+#[derive(Debug, Default)]
+pub(crate) struct LineSplitter {
+    partial: Vec<u8>,
+    max_len: Option<usize>,
+}
+
+impl LineSplitter {
+    pub(crate) fn with_max_len(max_len: Option<usize>) -> Self {
+        Self {
+            partial: Vec::new(),
+            max_len,
+        }
+    }
+
+    pub(crate) fn push(&mut self, data: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut rest = data;
+
+        while let Some(pos) = rest.iter().position(|b| *b == b'\n') {
+            self.partial.extend_from_slice(&rest[..pos]);
+            if self.partial.last() == Some(&b'\r') {
+                self.partial.pop();
+            }
+            self.spill(&mut lines);
+            lines.push(String::from_utf8_lossy(&self.partial).into_owned());
+            self.partial.clear();
+            rest = &rest[pos + 1..];
+        }
+        self.partial.extend_from_slice(rest);
+        self.spill(&mut lines);
+
+        lines
+    }
+
+    /// Hands out `max_len` sized pieces until what's buffered fits again.
+    fn spill(&mut self, lines: &mut Vec<String>) {
+        let max_len = match self.max_len {
+            Some(max_len) => max_len.max(1),
+            None => return,
+        };
+
+        while self.partial.len() > max_len {
+            // A character longer than `max_len` on its own waits for the line to
+            // go on, so no empty piece is left behind it.
+            let at = char_boundary(&self.partial, max_len);
+            if at == self.partial.len() {
+                break;
+            }
+            let rest = self.partial.split_off(at);
+            lines.push(String::from_utf8_lossy(&self.partial).into_owned());
+            self.partial = rest;
+        }
+    }
+
+    /// Whatever was left without a newline when the stream ended.
+    pub(crate) fn finish(&mut self) -> Option<String> {
+        if self.partial.is_empty() {
+            return None;
+        }
+
+        let line = String::from_utf8_lossy(&self.partial).into_owned();
+        self.partial.clear();
+        Some(line)
+    }
+}
+
+/// Where to cut `bytes` for a piece of at most `max_len` bytes without splitting a
+/// UTF-8 character: `max_len`, moved back to the start of the character it falls
+/// in. When that character starts the piece it goes in whole instead. Bytes that
+/// aren't UTF-8 anyway are cut at `max_len`.
+fn char_boundary(bytes: &[u8], max_len: usize) -> usize {
+    let continues = |at: usize| {
+        bytes
+            .get(at)
+            .is_some_and(|b| b & 0b1100_0000 == 0b1000_0000)
+    };
+
+    let start = (max_len.saturating_sub(3)..=max_len)
+        .rev()
+        .find(|&at| !continues(at));
+    match start {
+        Some(0) => (1..bytes.len())
+            .find(|&at| !continues(at))
+            .unwrap_or(bytes.len()),
+        Some(at) => at,
+        None => max_len,
+    }
+}
+
+/// A line's message with the time the daemon logged it at, when known.
+type Stamped = (Option<SystemTime>, String);
+
+/// Holds back consecutive copies of a line, standing in for them with a single
+/// `... repeated K times` once a different line comes or the stream ends. Lines
+/// count as copies when their messages match, whatever their timestamps; the
+/// marker carries the timestamp of the last copy.
+#[derive(Debug, Default)]
+pub(crate) struct Repeats {
+    last: Option<String>,
+    last_at: Option<SystemTime>,
+    count: usize,
+}
+
+impl Repeats {
+    fn push(&mut self, (at, line): Stamped) -> Vec<Stamped> {
+        if self.last.as_ref() == Some(&line) {
+            self.count += 1;
+            self.last_at = at;
+            return Vec::new();
+        }
+
+        let mut lines: Vec<Stamped> = self.finish().into_iter().collect();
+        lines.push((at, line.clone()));
+        self.last = Some(line);
+        self.last_at = at;
+        lines
+    }
+
+    /// The marker for the repeats held back so far, if there were any.
+    fn finish(&mut self) -> Option<Stamped> {
+        let marker = match std::mem::take(&mut self.count) {
+            0 => return None,
+            1 => "... repeated 1 time".to_owned(),
+            n => format!("... repeated {} times", n),
+        };
+        Some((self.last_at, marker))
+    }
+}
+
+/// How [`LineDecoder`] post-processes lines.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct LineOptions {
+    /// See [`LineSplitter`].
+    pub(crate) max_len: Option<usize>,
+    pub(crate) collapse_repeats: bool,
+    pub(crate) strip_ansi: bool,
+    /// Lines start with the RFC 3339 timestamp `timestamps=1` asks the daemon for.
+    pub(crate) timestamps: bool,
+    /// Only keep JSON lines with a `level` at least this severe.
+    pub(crate) min_level: Option<LogLevel>,
+    /// With `min_level`, also drop the lines that have no level to go by.
+    pub(crate) drop_unstructured: bool,
+}
+
+/// One stream's lines, cleaned up as asked to.
+#[derive(Debug)]
+struct StreamLines {
+    ansi: Option<AnsiStripper>,
+    splitter: LineSplitter,
+    timestamps: bool,
+    min_level: Option<LogLevel>,
+    drop_unstructured: bool,
+    repeats: Option<Repeats>,
+}
+
+impl StreamLines {
+    fn new(options: LineOptions) -> Self {
+        Self {
+            ansi: if options.strip_ansi {
+                Some(AnsiStripper::default())
+            } else {
+                None
+            },
+            splitter: LineSplitter::with_max_len(options.max_len),
+            timestamps: options.timestamps,
+            min_level: options.min_level,
+            drop_unstructured: options.drop_unstructured,
+            repeats: if options.collapse_repeats {
+                Some(Repeats::default())
+            } else {
+                None
+            },
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) -> Vec<Stamped> {
+        let lines = match &mut self.ansi {
+            Some(ansi) => self.splitter.push(&ansi.strip(data)),
+            None => self.splitter.push(data),
+        };
+        self.process(lines)
+    }
+
+    /// Everything still held back once the stream is over, each with whether it is
+    /// the line the stream ended in the middle of.
+    fn finish(&mut self) -> Vec<(Stamped, bool)> {
+        let last = self.splitter.finish();
+        let cut = last.is_some();
+        let mut lines = self
+            .process(last.into_iter().collect())
+            .into_iter()
+            .map(|line| (line, false))
+            .collect::<Vec<_>>();
+        // Unless it was filtered out or folded into the repeats, the cut line is
+        // the last thing processing hands back.
+        if let (true, Some(line)) = (cut, lines.last_mut()) {
+            line.1 = true;
+        }
+        lines.extend(
+            self.repeats
+                .as_mut()
+                .and_then(Repeats::finish)
+                .map(|marker| (marker, false)),
+        );
+        lines
+    }
+
+    fn process(&mut self, lines: Vec<String>) -> Vec<Stamped> {
+        let timestamps = self.timestamps;
+        let (min_level, drop_unstructured) = (self.min_level, self.drop_unstructured);
+        let stamped = lines
+            .into_iter()
+            .map(|line| {
+                if timestamps {
+                    split_timestamp(line)
+                } else {
+                    (None, line)
+                }
+            })
+            .filter(|(_, line)| match min_level {
+                None => true,
+                Some(min) => LogLevel::of(line).map_or(!drop_unstructured, |level| level >= min),
+            });
+
+        match &mut self.repeats {
+            Some(repeats) => stamped.flat_map(|line| repeats.push(line)).collect(),
+            None => stamped.collect(),
+        }
+    }
+}
+
+/// Splits the daemon's `<timestamp> ` prefix off a line. A line that doesn't
+/// start with one, as from a daemon or log driver that ignores `timestamps=1`, is
+/// kept whole; one that is nothing but a timestamp is an empty line.
+fn split_timestamp(line: String) -> Stamped {
+    let parsed = match line.split_once(' ') {
+        Some((prefix, _)) => timestamp::parse(prefix).map(|at| (at, prefix.len() + 1)),
+        None => timestamp::parse(&line).map(|at| (at, line.len())),
+    };
+
+    match parsed {
+        Some((at, len)) => (Some(at), line[len..].to_owned()),
+        None => (None, line),
+    }
+}
+
+/// Turns a logs endpoint body into [`LogLine`]s, keeping stdout and stderr apart so
+/// a partial line on one never gets glued to the other.
+#[derive(Debug)]
+pub(crate) struct LineDecoder {
+    container_id: String,
+    frames: FrameDecoder,
+    stdout: StreamLines,
+    stderr: StreamLines,
+}
+
+impl LineDecoder {
+    pub(crate) fn new(container_id: String, tty: bool, options: LineOptions) -> Self {
+        Self {
+            container_id,
+            frames: FrameDecoder::new(tty),
+            stdout: StreamLines::new(options),
+            stderr: StreamLines::new(options),
+        }
+    }
+
+    fn line(&self, stream: StreamKind, (timestamp, message): Stamped, incomplete: bool) -> LogLine {
+        LogLine {
+            container_id: self.container_id.clone(),
+            stream,
+            message,
+            timestamp,
+            incomplete,
+        }
+    }
+
+    pub(crate) fn push(&mut self, chunk: Bytes) -> Vec<LogLine> {
+        let mut lines = Vec::new();
+
+        for (stream, payload) in self.frames.push(chunk) {
+            let stamped = match stream {
+                StreamKind::Stderr => self.stderr.push(&payload),
+                _ => self.stdout.push(&payload),
+            };
+            lines.extend(stamped.into_iter().map(|l| self.line(stream, l, false)));
+        }
+
+        lines
+    }
+
+    pub(crate) fn finish(&mut self) -> Vec<LogLine> {
+        let stdout = self.stdout.finish();
+        let stderr = self.stderr.finish();
+
+        stdout
+            .into_iter()
+            .map(|(l, cut)| self.line(StreamKind::Stdout, l, cut))
+            .chain(
+                stderr
+                    .into_iter()
+                    .map(|(l, cut)| self.line(StreamKind::Stderr, l, cut)),
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{sse_event, LineDecoder, LineOptions, LineSplitter, LogLine};
+    use crate::{LogLevel, StreamKind};
+
+    #[test]
+    fn short_id_truncates_full_id() {
+        let mut line = LogLine {
+            container_id: "8dfafdbc3a40b5c0e3c4a1f6a5b2e7d9c8b7a6f5e4d3c2b1a0f9e8d7c6b5a4f3".into(),
+            stream: StreamKind::Stdout,
+            message: "hi".into(),
+            timestamp: None,
+            incomplete: false,
+        };
+        assert_eq!(line.short_id(), "8dfafdbc3a40");
+
+        line.container_id = "8dfa".into();
+        assert_eq!(line.short_id(), "8dfa");
+    }
+
+    #[test]
+    fn lines_format_as_sse_events() {
+        let line = LogLine {
+            container_id: "abc".into(),
+            stream: StreamKind::Stderr,
+            message: "say \"hi\"\r".into(),
+            timestamp: Some(UNIX_EPOCH + Duration::new(1_704_067_200, 5)),
+            incomplete: false,
+        };
+        assert_eq!(
+            line.to_sse(),
+            "data: {\"container_id\":\"abc\",\"message\":\"say \\\"hi\\\"\\r\",\"stream\":\"stderr\",\
+             \"timestamp\":\"2024-01-01T00:00:00.000000005Z\"}\n\n"
+        );
+
+        assert_eq!(
+            sse_event("one\ntwo\r\nthree\rfour"),
+            "data: one\ndata: two\ndata: three\ndata: four\n\n"
+        );
+        assert_eq!(sse_event(""), "data: \n\n");
+    }
+
+    #[test]
+    fn lines_split_across_chunks() {
+        let mut splitter = LineSplitter::default();
+
+        assert_eq!(splitter.push(b"one\ntw"), vec!["one"]);
+        assert_eq!(splitter.push(b"o\nthree"), vec!["two"]);
+        assert_eq!(splitter.finish().as_deref(), Some("three"));
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn crlf_endings_are_trimmed() {
+        let mut splitter = LineSplitter::default();
+
+        assert_eq!(splitter.push(b"one\r\ntwo\r"), vec!["one"]);
+        assert_eq!(
+            splitter.push(b"\nthree\r\r\n\r\n"),
+            vec!["two", "three\r", ""]
+        );
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn long_lines_come_in_pieces() {
+        let mut splitter = LineSplitter::with_max_len(Some(4));
+
+        assert_eq!(splitter.push(b"abcdefghij"), vec!["abcd", "efgh"]);
+        assert_eq!(splitter.push(b"kl\nmnop\n"), vec!["ijkl", "mnop"]);
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn long_lines_are_cut_between_characters() {
+        let mut splitter = LineSplitter::with_max_len(Some(4));
+
+        // "€" and "😀" are three and four bytes long, straddling the cuts.
+        assert_eq!(splitter.push("ab€cd".as_bytes()), vec!["ab", "€c"]);
+        assert_eq!(splitter.push("😀e\n".as_bytes()), vec!["d", "😀", "e"]);
+
+        let mut narrow = LineSplitter::with_max_len(Some(2));
+        assert_eq!(narrow.push("€€\n".as_bytes()), vec!["€", "€"]);
+        assert_eq!(narrow.push(b"\x80\x80\x80\x80\x80\n").len(), 3);
+    }
+
+    #[test]
+    fn repeats_collapse_into_a_marker() {
+        let options = LineOptions {
+            collapse_repeats: true,
+            ..Default::default()
+        };
+        let mut decoder = LineDecoder::new("abc".into(), true, options);
+
+        let messages =
+            |lines: Vec<LogLine>| lines.into_iter().map(|l| l.message).collect::<Vec<_>>();
+        assert_eq!(
+            messages(decoder.push("retrying\nretrying\nretrying\nconnected\n".into())),
+            vec!["retrying", "... repeated 2 times", "connected"]
+        );
+        assert_eq!(
+            messages(decoder.push("connected\nconn".into())),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            messages(decoder.finish()),
+            vec!["... repeated 1 time", "conn"]
+        );
+    }
+
+    #[test]
+    fn lines_below_the_level_are_dropped() {
+        let body = concat!(
+            "{\"level\":\"info\",\"msg\":\"listening\"}\n",
+            "{\"level\":\"error\",\"msg\":\"db down\"}\n",
+            "plain text\n",
+            "{\"level\":\"fatal\",\"msg\":\"giving up\"}\n",
+        );
+        let messages = |drop_unstructured| {
+            let options = LineOptions {
+                min_level: Some(LogLevel::Error),
+                drop_unstructured,
+                ..Default::default()
+            };
+            let mut decoder = LineDecoder::new("abc".into(), true, options);
+            let lines = decoder.push(body.into());
+            lines.into_iter().map(|l| l.message).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            messages(false),
+            vec![
+                r#"{"level":"error","msg":"db down"}"#,
+                "plain text",
+                r#"{"level":"fatal","msg":"giving up"}"#
+            ]
+        );
+        assert_eq!(messages(true).len(), 2);
+    }
+
+    #[test]
+    fn lines_without_a_timestamp_are_kept_whole() {
+        let options = LineOptions {
+            timestamps: true,
+            ..Default::default()
+        };
+        let mut decoder = LineDecoder::new("abc".into(), true, options);
+
+        let lines = decoder.push(
+            concat!(
+                "2024-01-01T00:00:00.000000005Z started\n",
+                "listening on :80\n",
+                "2024-13-45T00:00:00Z is not a time\n",
+                "2024-01-01T00:00:01Z\n",
+            )
+            .into(),
+        );
+        let stamped = lines
+            .into_iter()
+            .map(|l| (l.timestamp, l.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stamped,
+            vec![
+                (
+                    Some(UNIX_EPOCH + Duration::new(1_704_067_200, 5)),
+                    "started".into()
+                ),
+                (None, "listening on :80".into()),
+                (None, "2024-13-45T00:00:00Z is not a time".into()),
+                (
+                    Some(UNIX_EPOCH + Duration::new(1_704_067_201, 0)),
+                    "".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn ansi_is_stripped_before_splitting() {
+        let options = LineOptions {
+            strip_ansi: true,
+            ..Default::default()
+        };
+        let mut decoder = LineDecoder::new("abc".into(), true, options);
+
+        let mut lines = decoder.push("\x1b[32mok\x1b[0m\n\x1b[3".into());
+        lines.extend(decoder.push("3mwarn\x1b[0m\n".into()));
+        let messages = lines.into_iter().map(|l| l.message).collect::<Vec<_>>();
+        assert_eq!(messages, vec!["ok", "warn"]);
+    }
+}
@@ -0,0 +1,211 @@
+//! The followers' counters in the Prometheus text exposition format.
+
+use std::{fmt::Write, sync::atomic::Ordering};
+
+use crate::{container_log::Counters, DockerSystem, StreamKind};
+
+/// A counter family: its name after the prefix, its help text, and its value.
+type Family = (&'static str, &'static str, fn(&Counters) -> u64);
+
+impl DockerSystem {
+    /// The log followers' counters as Prometheus exposition text, ready to serve
+    /// from a `/metrics` endpoint:
+    ///
+    /// - `docker_log_stream_followers`: containers currently followed.
+    /// - `docker_log_stream_received_bytes_total`: body bytes read from the daemon,
+    ///   frame headers included.
+    /// - `docker_log_stream_lines_total`: newline-terminated lines in those bytes.
+    /// - `docker_log_stream_reconnects_total`: follows reopened after the daemon
+    ///   connection dropped, over every follow made through this system, such as
+    ///   supervised ones with [`ContainerLogOptions::resume`](crate::ContainerLogOptions::resume).
+    ///
+    /// The bytes and lines counters carry `container` (the short id) and `stream`
+    /// labels. A container's counters go away once it stops and is dropped by a
+    /// refresh.
+    pub fn metrics_prometheus(&self) -> String {
+        let mut logs = self.container_logs.values().collect::<Vec<_>>();
+        logs.sort_by(|a, b| a.id.cmp(&b.id));
+        let following = logs.iter().filter(|log| !log.is_finished()).count();
+
+        let mut out = String::new();
+        family(
+            &mut out,
+            "followers",
+            "gauge",
+            "Containers whose logs are being followed.",
+        );
+        let _ = writeln!(out, "docker_log_stream_followers {}", following);
+
+        let counters: [Family; 2] = [
+            (
+                "received_bytes_total",
+                "Bytes of log bodies read from the daemon.",
+                |c| c.bytes.load(Ordering::Relaxed),
+            ),
+            ("lines_total", "Log lines read from the daemon.", |c| {
+                c.lines.load(Ordering::Relaxed)
+            }),
+        ];
+        for (name, help, value) in counters {
+            family(&mut out, name, "counter", help);
+            for log in &logs {
+                for (stream, label) in [
+                    (StreamKind::Stdout, "stdout"),
+                    (StreamKind::Stderr, "stderr"),
+                ] {
+                    let _ = writeln!(
+                        out,
+                        "docker_log_stream_{}{{container=\"{}\",stream=\"{}\"}} {}",
+                        name,
+                        log.id,
+                        label,
+                        value(log.counters(stream))
+                    );
+                }
+            }
+        }
+
+        family(
+            &mut out,
+            "reconnects_total",
+            "counter",
+            "Follows reopened after the daemon connection dropped.",
+        );
+        let _ = writeln!(
+            out,
+            "docker_log_stream_reconnects_total {}",
+            self.transport.reconnects().load(Ordering::Relaxed)
+        );
+
+        out
+    }
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP docker_log_stream_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE docker_log_stream_{} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use futures_lite::StreamExt;
+    use hyper::{Body, Response};
+
+    use crate::{mock, AttachOptions, ContainerLogOptions, DockerSystem};
+
+    #[tokio::test]
+    async fn counters_render_as_exposition_text() {
+        let id = "a".repeat(12);
+        let listing = format!(r#"[{{"Id":"{}"}}]"#, "a".repeat(64));
+        let daemon = mock::MockDaemon::start(move |req| {
+            if req.uri().path() == "/containers/json" {
+                mock::json(200, &listing)
+            } else if req.uri().path().ends_with("/json") {
+                mock::json(200, r#"{"Config":{"Tty":false}}"#)
+            } else if req.uri().query().unwrap_or_default().contains("stdout=1") {
+                Response::new(mock::frame(1, b"hello\nworld\n").into())
+            } else {
+                Response::new(Body::empty())
+            }
+        });
+
+        let mut system = DockerSystem::with_transport(daemon.transport())
+            .await
+            .unwrap();
+        for log in system.container_logs.values_mut() {
+            while log.recv_any().await.is_some() {}
+        }
+
+        let metrics = system.metrics_prometheus();
+        assert!(metrics.contains("# TYPE docker_log_stream_followers gauge\n"));
+        assert!(metrics.contains("# TYPE docker_log_stream_received_bytes_total counter\n"));
+        for sample in [
+            format!(
+                r#"received_bytes_total{{container="{}",stream="stdout"}} 20"#,
+                id
+            ),
+            format!(
+                r#"received_bytes_total{{container="{}",stream="stderr"}} 0"#,
+                id
+            ),
+            format!(r#"lines_total{{container="{}",stream="stdout"}} 2"#, id),
+            format!(r#"lines_total{{container="{}",stream="stderr"}} 0"#, id),
+            "reconnects_total 0".to_owned(),
+        ] {
+            assert!(
+                metrics.contains(&format!("\ndocker_log_stream_{}\n", sample)),
+                "{}",
+                metrics
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn supervised_follows_count_their_reconnects() {
+        let listing = format!(r#"[{{"Id":"{}"}}]"#, "a".repeat(64));
+        let follows = Arc::new(AtomicUsize::new(0));
+        let events = Arc::new(Mutex::new(None));
+        let daemon = mock::MockDaemon::start(move |req| {
+            let path = req.uri().path();
+            if path == "/containers/json" {
+                return mock::json(200, &listing);
+            }
+            if path == "/events" {
+                let (tx, body) = Body::channel();
+                *events.lock().unwrap() = Some(tx);
+                return Response::new(body);
+            }
+            if path.ends_with("/json") {
+                return mock::json(200, r#"{"Config":{"Tty":false},"State":{"Running":true}}"#);
+            }
+            // The first follow is cut off, the one reopened after it stays up.
+            let (mut tx, body) = Body::channel();
+            let first = follows.fetch_add(1, Ordering::SeqCst) == 0;
+            tokio::spawn(async move {
+                let line = if first { "hello\n" } else { "again\n" };
+                let _ = tx.send_data(mock::frame(1, line.as_bytes()).into()).await;
+                if first {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    tx.abort();
+                } else {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                }
+            });
+            Response::new(body)
+        });
+
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let options = AttachOptions {
+            log: ContainerLogOptions {
+                stderr: false,
+                resume: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut lines = system.attach_all_with(options).await.unwrap();
+        let mut messages = Vec::new();
+        for _ in 0..2 {
+            messages.push(lines.next().await.unwrap().message);
+        }
+        assert_eq!(messages, vec!["hello", "again"]);
+
+        let reconnected = tokio::time::timeout(Duration::from_secs(5), async {
+            while !system
+                .metrics_prometheus()
+                .contains("\ndocker_log_stream_reconnects_total 1\n")
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        assert!(reconnected.await.is_ok(), "{}", system.metrics_prometheus());
+    }
+}
@@ -0,0 +1,221 @@
+//! A fake daemon listening on a temporary unix socket, so tests don't need docker.
+
+use std::{
+    convert::Infallible,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use hyperlocal::UnixServerExt;
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixListener,
+    task::JoinHandle,
+};
+
+use crate::transport::Transport;
+
+pub(crate) struct MockDaemon {
+    pub(crate) socket: PathBuf,
+    requests: Arc<Mutex<Vec<String>>>,
+    connections: Arc<AtomicUsize>,
+    handle: JoinHandle<()>,
+    _dir: TempDir,
+}
+
+impl MockDaemon {
+    /// Serves every request with `handler`. Must be called from inside a runtime.
+    pub(crate) fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Request<Body>) -> Response<Body> + Send + Sync + 'static,
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("docker.sock");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let handler = Arc::new(handler);
+
+        let log = requests.clone();
+        let accepted = connections.clone();
+        let server = Server::bind_unix(&socket)
+            .unwrap()
+            .serve(make_service_fn(move |_| {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let handler = handler.clone();
+                let log = log.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                        let path = req
+                            .uri()
+                            .path_and_query()
+                            .map(|p| p.as_str().to_owned())
+                            .unwrap_or_default();
+                        log.lock().unwrap().push(path);
+                        let response = handler(&req);
+                        async move { Ok::<_, Infallible>(response) }
+                    }))
+                }
+            }));
+
+        let handle = tokio::spawn(async move {
+            server.await.unwrap();
+        });
+
+        Self {
+            socket,
+            requests,
+            connections,
+            handle,
+            _dir: dir,
+        }
+    }
+
+    pub(crate) fn transport(&self) -> Transport {
+        Transport::new(&self.socket)
+    }
+
+    /// Path and query of every request received so far, in order.
+    pub(crate) fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// How many connections have been accepted on the socket.
+    pub(crate) fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for MockDaemon {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// A daemon that answers every request by writing `response` verbatim and then
+/// leaving the connection open, for wire details hyper's server won't produce.
+/// Container inspects get a non-TTY container instead, so follows can be opened.
+pub(crate) struct RawDaemon {
+    socket: PathBuf,
+    handle: JoinHandle<()>,
+    _dir: TempDir,
+}
+
+const INSPECT: &[u8] = b"HTTP/1.1 200 OK\r\n\
+    Content-Type: application/json\r\n\
+    Content-Length: 24\r\n\
+    \r\n\
+    {\"Config\":{\"Tty\":false}}";
+
+impl RawDaemon {
+    pub(crate) fn start(response: &'static [u8]) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("docker.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        let handle = tokio::spawn(async move {
+            loop {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    // The inspect's connection goes back to the pool, so the
+                    // follow may well come in on it next.
+                    loop {
+                        let mut request = Vec::new();
+                        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                            match conn.read(&mut buf).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(n) => request.extend_from_slice(&buf[..n]),
+                            }
+                        }
+                        let inspect = request
+                            .split(|b| *b == b' ')
+                            .nth(1)
+                            .is_some_and(|path| path.ends_with(b"/json"));
+                        if !inspect {
+                            break;
+                        }
+                        conn.write_all(INSPECT).await.unwrap();
+                    }
+                    conn.write_all(response).await.unwrap();
+                    while let Ok(n) = conn.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        Self {
+            socket,
+            handle,
+            _dir: dir,
+        }
+    }
+
+    pub(crate) fn transport(&self) -> Transport {
+        Transport::new(&self.socket)
+    }
+}
+
+impl Drop for RawDaemon {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+pub(crate) fn json(status: u16, body: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_owned()))
+        .unwrap()
+}
+
+/// Wraps `payload` in the 8 byte header docker multiplexes log streams with.
+pub(crate) fn frame(stream: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![stream, 0, 0, 0];
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Everything traced on this thread while the guard is held.
+#[derive(Clone, Default)]
+pub(crate) struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    pub(crate) fn start() -> (Self, tracing::subscriber::DefaultGuard) {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        (captured, tracing::subscriber::set_default(subscriber))
+    }
+
+    pub(crate) fn output(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures_lite::Stream;
+use tokio::{sync::mpsc::UnboundedReceiver, task::JoinHandle};
+
+use crate::{
+    budget::{Budget, Weigh},
+    ContainerLog, StreamKind,
+};
+
+/// A [`Stream`] fed by a background task. Dropping the stream stops the task.
+#[derive(Debug)]
+pub struct TaskStream<T> {
+    rx: UnboundedReceiver<T>,
+    handle: JoinHandle<()>,
+    /// Given back to as items are received.
+    budget: Option<(Arc<Budget>, Weigh<T>)>,
+}
+
+impl<T> TaskStream<T> {
+    pub(crate) fn new(rx: UnboundedReceiver<T>, handle: JoinHandle<()>) -> Self {
+        Self::within(rx, handle, None)
+    }
+
+    /// A stream whose items were admitted against `budget`.
+    pub(crate) fn within(
+        rx: UnboundedReceiver<T>,
+        handle: JoinHandle<()>,
+        budget: Option<(Arc<Budget>, Weigh<T>)>,
+    ) -> Self {
+        Self { rx, handle, budget }
+    }
+}
+
+impl<T> Stream for TaskStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let polled = self.rx.poll_recv(cx);
+
+        if let (Poll::Ready(Some(item)), Some((budget, weigh))) = (&polled, &self.budget) {
+            budget.give(weigh(item));
+        }
+
+        polled
+    }
+}
+
+impl<T> Drop for TaskStream<T> {
+    fn drop(&mut self) {
+        self.handle.abort();
+        if let Some((budget, _)) = &self.budget {
+            budget.close();
+        }
+    }
+}
+
+/// A [`ContainerLog`]'s stdout and stderr as a single [`Stream`], from
+/// [`ContainerLog::into_stream`]. Ends once both have.
+#[derive(Debug)]
+pub struct LogStream {
+    log: ContainerLog<Bytes>,
+    /// Which stream is polled first, swapped after every item so a busy stream
+    /// can't starve the other.
+    stderr_first: bool,
+}
+
+impl ContainerLog<Bytes> {
+    /// Merges stdout and stderr into one stream of chunks, each tagged with the
+    /// stream it came from.
+    pub fn into_stream(self) -> LogStream {
+        LogStream {
+            log: self,
+            stderr_first: false,
+        }
+    }
+}
+
+impl LogStream {
+    /// The id of the container being followed.
+    pub fn id(&self) -> &str {
+        &self.log.id
+    }
+}
+
+impl Stream for LogStream {
+    type Item = (StreamKind, Bytes);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let order = if self.stderr_first {
+            [StreamKind::Stderr, StreamKind::Stdout]
+        } else {
+            [StreamKind::Stdout, StreamKind::Stderr]
+        };
+
+        let mut closed = 0;
+        for stream in order {
+            let rx = match stream {
+                StreamKind::Stderr => &mut self.log.stderr,
+                _ => &mut self.log.stdout,
+            };
+            match rx.poll_recv(cx) {
+                Poll::Ready(Some(chunk)) => {
+                    self.stderr_first = stream == StreamKind::Stdout;
+                    return Poll::Ready(Some((stream, chunk)));
+                }
+                Poll::Ready(None) => closed += 1,
+                Poll::Pending => {}
+            }
+        }
+
+        if closed == order.len() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::StreamExt;
+    use hyper::Response;
+
+    use crate::{mock, ContainerLog, StreamKind};
+
+    #[tokio::test]
+    async fn container_log_streams_both_outputs() {
+        let daemon = mock::MockDaemon::start(|req| {
+            if req.uri().path().ends_with("/json") {
+                return mock::json(200, r#"{"Config":{"Tty":false}}"#);
+            }
+            let query = req.uri().query().unwrap_or_default();
+            let body = if query.contains("stdout=1") {
+                mock::frame(1, b"out\n")
+            } else {
+                mock::frame(2, b"err\n")
+            };
+            Response::new(body.into())
+        });
+        let log = ContainerLog::open(&daemon.transport(), "abc".into(), &Default::default())
+            .await
+            .unwrap();
+
+        let stream = log.into_stream();
+        assert_eq!(stream.id(), "abc");
+        let mut chunks: Vec<_> = stream
+            .map(|(stream, chunk)| (stream, chunk.len()))
+            .collect()
+            .await;
+        chunks.sort_by_key(|(stream, _)| *stream == StreamKind::Stderr);
+        assert_eq!(
+            chunks,
+            vec![(StreamKind::Stdout, 8 + 4), (StreamKind::Stderr, 8 + 4)]
+        );
+    }
+}
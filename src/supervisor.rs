@@ -0,0 +1,1050 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use futures_lite::StreamExt;
+use hyper::StatusCode;
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    task::JoinSet,
+};
+
+use crate::{
+    budget::{Budget, Weigh},
+    container::{self, ContainerInfo},
+    events::{events, DockerEvent, FollowEvent, HostEvent, ServiceLine},
+    line::LogLine,
+    stream::TaskStream,
+    transport::{filters_query, Transport},
+    ContainerLog, ContainerLogOptions, DockerError, DockerSystem, Since,
+};
+
+/// Which containers [`DockerSystem::attach_all_with`] attaches to, and how.
+#[derive(Debug, Clone, Default)]
+pub struct AttachOptions {
+    /// Used for every container's follow.
+    pub log: ContainerLogOptions,
+    /// Only attach to containers carrying all of these labels, each given as
+    /// `key` or `key=value`.
+    pub labels: Vec<String>,
+    /// Caps how many bytes of log messages all containers together may have
+    /// waiting for the consumer. Once it is reached every container's follow
+    /// pauses until lines are taken off the stream, so one chatty container can't
+    /// grow memory without bound. A single line larger than the cap is still
+    /// delivered, once everything before it has been consumed.
+    pub max_buffered_bytes: Option<usize>,
+}
+
+/// Picks which containers [`DockerSystem::attach_all_matching`] attaches to.
+type Predicate = Arc<dyn Fn(&ContainerInfo) -> bool + Send + Sync>;
+
+/// Attaches to every running container, and to every container that starts
+/// afterwards, that `predicate` accepts, forwarding what `on_line`/`on_event` map
+/// their output to. `on_line` is given the summary of the container each line
+/// comes from; without a predicate containers that start later aren't looked up,
+/// and their summary only has the id and name from the event.
+///
+/// The event subscription is opened before the running containers are listed so
+/// nothing that starts in between is missed. A container that restarts under the
+/// same id is followed on from when it stopped, not from the start of its log.
+/// The follows belong to the supervising task, so dropping the stream closes
+/// every one of them.
+async fn supervise<T: Send + 'static>(
+    transport: Transport,
+    options: AttachOptions,
+    predicate: Option<Predicate>,
+    on_line: fn(&ContainerInfo, LogLine) -> T,
+    on_event: fn(DockerEvent) -> Option<T>,
+    weigh: Weigh<T>,
+) -> Result<TaskStream<T>, DockerError> {
+    let filters = if options.labels.is_empty() {
+        serde_json::json!({})
+    } else {
+        serde_json::json!({ "label": options.labels })
+    };
+
+    let mut events = events(&transport, filters.clone()).await?;
+    let running = container::list(&transport, &filters_query(&filters)).await?;
+
+    let budget = options
+        .max_buffered_bytes
+        .map(|max| Arc::new(Budget::new(max)));
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let stream_budget = budget.clone().map(|budget| (budget, weigh));
+    let handle = tokio::spawn(async move {
+        let mut attached = HashSet::new();
+        let mut stopped_at = HashMap::new();
+        let mut follows = JoinSet::new();
+
+        let accepted = |info: &ContainerInfo| predicate.as_ref().is_none_or(|p| p(info));
+        let attach = |follows: &mut JoinSet<()>, info: ContainerInfo, log: ContainerLogOptions| {
+            // Forget the follows of containers that have stopped since.
+            while follows.try_join_next().is_some() {}
+
+            let info = Arc::new(info);
+            follows.spawn(follow(
+                transport.clone(),
+                info.id.clone(),
+                log,
+                budget.clone(),
+                tx.clone(),
+                move |line| on_line(&info, line),
+            ));
+        };
+        for info in running.into_iter().filter(|c| accepted(c)) {
+            attached.insert(info.id.clone());
+            attach(&mut follows, info, options.log.clone());
+        }
+
+        while let Some(event) = events.next().await {
+            // One line that didn't parse is no reason to stop watching; the stream
+            // ends by itself once the daemon's body does.
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    tracing::warn!(error = %e, "skipping an unreadable daemon event");
+                    continue;
+                }
+            };
+            let id = event.id().to_owned();
+            // A paused container's follow stays open and just goes quiet, so
+            // pausing and unpausing changes nothing about what is attached.
+            let (started, stopped) = match &event {
+                DockerEvent::Started { name, .. } => (Some(name.clone()), None),
+                DockerEvent::Stopped { at, .. } => (None, Some(at_or_now(*at))),
+                DockerEvent::Paused { .. } | DockerEvent::Unpaused { .. } => (None, None),
+            };
+
+            if let Some(item) = on_event(event) {
+                if tx.send(item).is_err() {
+                    return;
+                }
+            }
+
+            if let Some(at) = stopped {
+                attached.remove(&id);
+                stopped_at.insert(id, at);
+                continue;
+            }
+            let name = match started {
+                Some(name) if !attached.contains(&id) => name,
+                _ => continue,
+            };
+            let info = match &predicate {
+                Some(predicate) => starting_accepted(&transport, &filters, &id, predicate).await,
+                None => ContainerInfo::from_json(&serde_json::json!({
+                    "Id": id,
+                    "Names": [format!("/{}", name)],
+                }))
+                .ok(),
+            };
+            if let Some(info) = info {
+                let log = match stopped_at.remove(&id) {
+                    Some(at) if options.log.since.is_none() => ContainerLogOptions {
+                        since: Some(Since::At(at)),
+                        ..options.log.clone()
+                    },
+                    _ => options.log.clone(),
+                };
+                attached.insert(id);
+                attach(&mut follows, info, log);
+            }
+        }
+    });
+
+    Ok(TaskStream::within(rx, handle, stream_budget))
+}
+
+/// The summary of the container `id` that just started, looked up with
+/// `filters`, if `predicate` accepts it. One gone again already is not.
+async fn starting_accepted(
+    transport: &Transport,
+    filters: &serde_json::Value,
+    id: &str,
+    predicate: &Predicate,
+) -> Option<ContainerInfo> {
+    let mut filters = filters.clone();
+    filters["id"] = serde_json::json!([id]);
+    container::list(transport, &filters_query(&filters))
+        .await
+        .ok()?
+        .into_iter()
+        .find(|c| c.id == id && predicate(c))
+}
+
+/// Forwards one container's output as lines until it stops. Containers that are
+/// gone again before we manage to attach are skipped; any other failure to attach
+/// is logged, so a misconfiguration doesn't look like a quiet container.
+async fn follow<T>(
+    transport: Transport,
+    id: String,
+    options: ContainerLogOptions,
+    budget: Option<Arc<Budget>>,
+    tx: UnboundedSender<T>,
+    on_line: impl Fn(LogLine) -> T + Clone,
+) {
+    let log = match ContainerLog::open_lines_within(&transport, id.clone(), &options, budget).await
+    {
+        Ok(log) => log,
+        Err(DockerError::Http { status, .. }) if status == StatusCode::NOT_FOUND => return,
+        Err(e) => {
+            tracing::warn!(container = %id, error = %e, "could not follow the container's logs");
+            return;
+        }
+    };
+
+    let forward = |mut rx: UnboundedReceiver<LogLine>| {
+        let (tx, on_line) = (tx.clone(), on_line.clone());
+
+        async move {
+            // A quiet container may not send anything for ages, so don't wait on
+            // a line to notice nobody is receiving any more.
+            while let Some(line) = tokio::select! {
+                line = rx.recv() => line,
+                _ = tx.closed() => None,
+            } {
+                if tx.send(on_line(line)).is_err() {
+                    return;
+                }
+            }
+        }
+    };
+
+    tokio::join!(forward(log.stdout), forward(log.stderr));
+}
+
+/// When a container stopped, going by the daemon's clock as that is what `since`
+/// is compared against. Only a daemon that leaves the time out falls back on ours.
+fn at_or_now(at: Option<SystemTime>) -> SystemTime {
+    at.unwrap_or_else(SystemTime::now)
+}
+
+/// The service `info` is an instance of: its compose service, its swarm service,
+/// or failing both its own name.
+fn service_name(info: &ContainerInfo) -> String {
+    [
+        "com.docker.compose.service",
+        "com.docker.swarm.service.name",
+    ]
+    .iter()
+    .find_map(|label| info.labels.get(*label).cloned())
+    .or_else(|| {
+        let name = info.names.first()?;
+        Some(name.trim_start_matches('/').to_owned())
+    })
+    .unwrap_or_else(|| info.id.chars().take(12).collect())
+}
+
+/// Follows whichever container is called `name`, reattaching each time a new
+/// instance of it starts.
+async fn follow_restarts(
+    transport: Transport,
+    name: String,
+) -> Result<TaskStream<FollowEvent>, DockerError> {
+    let mut events = events(&transport, serde_json::json!({ "container": [name] })).await?;
+
+    let exact = format!("/{}", name);
+    let filters = serde_json::json!({ "name": [format!("^{}$", exact)] });
+    let running = container::list(&transport, &filters_query(&filters))
+        .await?
+        .into_iter()
+        .find(|c| c.names.contains(&exact));
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let handle = tokio::spawn(async move {
+        // Holds the follow of the current instance, if there is one.
+        let mut current = JoinSet::new();
+        if let Some(c) = running {
+            current.spawn(follow(
+                transport.clone(),
+                c.id,
+                Default::default(),
+                None,
+                tx.clone(),
+                FollowEvent::Log,
+            ));
+        }
+        let mut stopped_at = None;
+
+        while let Some(event) = events.next().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    tracing::warn!(container = %name, error = %e, "skipping an unreadable daemon event");
+                    continue;
+                }
+            };
+            let id = match event {
+                DockerEvent::Stopped { at, .. } => {
+                    stopped_at = Some(at_or_now(at));
+                    continue;
+                }
+                DockerEvent::Started { id, .. } => id,
+                DockerEvent::Paused { .. } | DockerEvent::Unpaused { .. } => continue,
+            };
+
+            // Let the previous instance's last lines drain so the boundary is clean.
+            if !current.is_empty() {
+                let _ = current.join_next().await;
+                let restarted = FollowEvent::Restarted {
+                    name: name.clone(),
+                    id: id.clone(),
+                };
+                if tx.send(restarted).is_err() {
+                    return;
+                }
+            }
+
+            // A restarted container keeps its id and its old log, so only what
+            // it wrote after going down is new.
+            let options = ContainerLogOptions {
+                since: stopped_at.take().map(Since::At),
+                ..Default::default()
+            };
+            current.spawn(follow(
+                transport.clone(),
+                id,
+                options,
+                None,
+                tx.clone(),
+                FollowEvent::Log,
+            ));
+        }
+    });
+
+    Ok(TaskStream::new(rx, handle))
+}
+
+impl DockerSystem {
+    /// Follows the container called `name` the way `docker compose logs -f` follows
+    /// a service: if it isn't running yet it is picked up once it starts, and when
+    /// it restarts (whether it keeps its id or is recreated under a new one) the
+    /// new instance is attached to with a [`FollowEvent::Restarted`] in between.
+    pub async fn follow_named(&self, name: &str) -> Result<TaskStream<FollowEvent>, DockerError> {
+        follow_restarts(self.transport.clone(), name.to_owned()).await
+    }
+
+    /// Follows the logs of every running container, picking up containers that
+    /// start later as well, merged into one stream of lines.
+    pub async fn attach_all(&self) -> Result<TaskStream<LogLine>, DockerError> {
+        self.attach_all_with(Default::default()).await
+    }
+
+    /// [`DockerSystem::attach_all`] restricted and configured by `options`.
+    pub async fn attach_all_with(
+        &self,
+        options: AttachOptions,
+    ) -> Result<TaskStream<LogLine>, DockerError> {
+        supervise(
+            self.transport.clone(),
+            options,
+            None,
+            |_, line| line,
+            |_| None,
+            LogLine::weigh,
+        )
+        .await
+    }
+
+    /// [`DockerSystem::attach_all_with`] restricted further to the containers
+    /// `predicate` accepts, for selection rules filters can't express. It sees each
+    /// running container's summary once up front, and each container that starts
+    /// later when it does.
+    pub async fn attach_all_matching(
+        &self,
+        options: AttachOptions,
+        predicate: impl Fn(&ContainerInfo) -> bool + Send + Sync + 'static,
+    ) -> Result<TaskStream<LogLine>, DockerError> {
+        supervise(
+            self.transport.clone(),
+            options,
+            Some(Arc::new(predicate)),
+            |_, line| line,
+            |_| None,
+            LogLine::weigh,
+        )
+        .await
+    }
+
+    /// Follows only stderr, of every running container and any that start later.
+    /// Lines are tagged with the container they came from.
+    pub async fn follow_stderr_all(&self) -> Result<TaskStream<LogLine>, DockerError> {
+        let log = ContainerLogOptions {
+            stdout: false,
+            ..Default::default()
+        };
+
+        self.attach_all_with(AttachOptions {
+            log,
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Follows every service of the compose project `name`, the same set of
+    /// containers `docker compose -p name logs -f` shows. Containers the project
+    /// scales up to later are attached as they start.
+    pub async fn follow_compose_project(
+        &self,
+        name: &str,
+    ) -> Result<TaskStream<LogLine>, DockerError> {
+        self.attach_all_with(AttachOptions {
+            labels: vec![format!("com.docker.compose.project={}", name)],
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Follows every container connected to `network`, a name or an id as with
+    /// [`DockerSystem::containers_in_network`], for a view of just the services
+    /// that talk to each other there. Containers are matched when they are first
+    /// seen, so one connected to the network after it started isn't picked up.
+    /// Fails with the daemon's 404 if there is no such network.
+    pub async fn follow_network(&self, network: &str) -> Result<TaskStream<LogLine>, DockerError> {
+        // Summaries only list the names of their networks, so look up which one
+        // an id stands for.
+        let inspect = self
+            .transport
+            .get_json(&format!("/networks/{}", network))
+            .await?;
+        let name = inspect
+            .get("Name")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| {
+                DockerError::InvalidResponse(format!("network {} has no name", network))
+            })?
+            .to_owned();
+
+        self.attach_all_matching(Default::default(), move |c| c.networks.contains(&name))
+            .await
+    }
+
+    /// Follows every container `options` selects as the services they run, the
+    /// way `docker compose logs -f` shows a project: each line is tagged with its
+    /// container's [`ServiceLine::service`], so the feed reads the same when a
+    /// container is restarted, recreated under a new id, or scaled to more
+    /// replicas. Containers that start later are attached to as they come up, and
+    /// a restarted one picks up after the lines it wrote before it went down.
+    pub async fn follow_services(
+        &self,
+        options: AttachOptions,
+    ) -> Result<TaskStream<ServiceLine>, DockerError> {
+        // Accepting everything still has containers that start later looked up,
+        // for the labels their service is named by.
+        supervise(
+            self.transport.clone(),
+            options,
+            Some(Arc::new(|_| true)),
+            |info, line| ServiceLine {
+                service: service_name(info),
+                line,
+            },
+            |_| None,
+            ServiceLine::weigh,
+        )
+        .await
+    }
+
+    /// Like [`DockerSystem::attach_all`], with the start/stop events that drive it
+    /// interleaved into the same stream. Pauses and unpauses come through too, to
+    /// explain a container going quiet; its follow stays open meanwhile.
+    ///
+    /// A container's `Started` event always comes before its first line, but lines
+    /// it wrote just before dying may still arrive after its `Stopped` event.
+    pub async fn host_events(&self) -> Result<TaskStream<HostEvent>, DockerError> {
+        supervise(
+            self.transport.clone(),
+            Default::default(),
+            None,
+            |_, line| HostEvent::Log(line),
+            |event| Some(HostEvent::Lifecycle(event)),
+            HostEvent::weigh,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use futures_lite::StreamExt;
+    use hyper::{body::Sender, Body, Response, StatusCode};
+
+    use crate::{
+        mock::{self, Captured},
+        transport::query_escape,
+        AttachOptions, DockerError, DockerEvent, DockerSystem, FollowEvent, HostEvent, StreamKind,
+    };
+
+    pub(crate) type Events = Arc<Mutex<Option<Sender>>>;
+
+    /// A daemon listing `listing` as running, holding `/events` open and answering
+    /// every logs request with `logs(container_id, query)`.
+    pub(crate) fn daemon<F>(listing: String, logs: F) -> (mock::MockDaemon, Events)
+    where
+        F: Fn(&str, &str) -> Response<Body> + Send + Sync + 'static,
+    {
+        let events: Events = Default::default();
+        let stash = events.clone();
+
+        let daemon = mock::MockDaemon::start(move |req| {
+            let path = req.uri().path();
+
+            if path == "/events" {
+                let (tx, body) = Body::channel();
+                *stash.lock().unwrap() = Some(tx);
+                Response::new(body)
+            } else if path.starts_with("/containers/json") {
+                mock::json(200, &listing)
+            } else if path.ends_with("/json") {
+                mock::json(200, r#"{"Config":{"Tty":false}}"#)
+            } else {
+                let id = path.split('/').nth(2).unwrap();
+                logs(id, req.uri().query().unwrap_or_default())
+            }
+        });
+
+        (daemon, events)
+    }
+
+    /// When the daemon says every event happened, far from the local clock.
+    pub(crate) const EVENT_TIME: &str = "1704067200";
+
+    pub(crate) async fn send_event(events: &Events, action: &str, id: &str, name: &str) {
+        let event = format!(
+            r#"{{"Type":"container","Action":"{}","Actor":{{"ID":"{}","Attributes":{{"name":"{}"}}}},"time":{},"timeNano":{}500000000}}"#,
+            action, id, name, EVENT_TIME, EVENT_TIME
+        );
+        send_line(events, &event).await;
+    }
+
+    /// Writes `line`, whatever it holds, to the held open `/events` body.
+    pub(crate) async fn send_line(events: &Events, line: &str) {
+        let mut tx = events.lock().unwrap().take().unwrap();
+        tx.send_data(format!("{}\n", line).into()).await.unwrap();
+        *events.lock().unwrap() = Some(tx);
+    }
+
+    /// Containers write `from {id}` on stdout and nothing on stderr.
+    pub(crate) fn greeting(id: &str, query: &str) -> Response<Body> {
+        if query.contains("stdout=1") {
+            Response::new(mock::frame(1, format!("from {}\n", id).as_bytes()).into())
+        } else {
+            Response::new(Body::empty())
+        }
+    }
+
+    #[tokio::test]
+    async fn host_events_interleave_lifecycle_and_logs() {
+        let (a, b) = ("a".repeat(64), "b".repeat(64));
+        let (daemon, events) = daemon(format!(r#"[{{"Id":"{}"}}]"#, a), greeting);
+
+        let system = DockerSystem::with_transport(daemon.transport())
+            .await
+            .unwrap();
+        let mut feed = system.host_events().await.unwrap();
+
+        match feed.next().await.unwrap() {
+            HostEvent::Log(line) => {
+                assert_eq!(line.container_id, a);
+                assert_eq!(line.stream, StreamKind::Stdout);
+                assert_eq!(line.message, format!("from {}", a));
+            }
+            e => panic!("unexpected event: {:?}", e),
+        }
+
+        send_event(&events, "start", &b, "web").await;
+
+        assert_eq!(
+            feed.next().await.unwrap(),
+            HostEvent::Lifecycle(DockerEvent::Started {
+                id: b.clone(),
+                name: "web".into()
+            })
+        );
+        match feed.next().await.unwrap() {
+            HostEvent::Log(line) => assert_eq!(line.message, format!("from {}", b)),
+            e => panic!("unexpected event: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn predicate_picks_containers_at_startup_and_later() {
+        let ids = ["a", "b", "c", "d"].map(|c| c.repeat(64));
+        let summary =
+            |id: &str, tier: &str| format!(r#"{{"Id":"{}","Labels":{{"tier":"{}"}}}}"#, id, tier);
+        let listing = Arc::new(Mutex::new(format!(
+            "[{},{}]",
+            summary(&ids[0], "web"),
+            summary(&ids[1], "db")
+        )));
+        let listed = listing.clone();
+        let events: Events = Default::default();
+        let stash = events.clone();
+        let daemon = mock::MockDaemon::start(move |req| match req.uri().path() {
+            "/events" => {
+                let (tx, body) = Body::channel();
+                *stash.lock().unwrap() = Some(tx);
+                Response::new(body)
+            }
+            "/containers/json" => mock::json(200, &listed.lock().unwrap()),
+            path if path.ends_with("/json") => mock::json(200, r#"{"Config":{"Tty":false}}"#),
+            path => greeting(path.split('/').nth(2).unwrap(), req.uri().query().unwrap()),
+        });
+
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let mut lines = system
+            .attach_all_matching(Default::default(), |c| c.labels["tier"] == "web")
+            .await
+            .unwrap();
+        assert_eq!(lines.next().await.unwrap().container_id, ids[0]);
+
+        *listing.lock().unwrap() = format!(
+            "[{},{},{},{}]",
+            summary(&ids[0], "web"),
+            summary(&ids[1], "db"),
+            summary(&ids[2], "web"),
+            summary(&ids[3], "db")
+        );
+        send_event(&events, "start", &ids[3], "cache").await;
+        send_event(&events, "start", &ids[2], "web-2").await;
+        assert_eq!(lines.next().await.unwrap().container_id, ids[2]);
+
+        let followed = |id: &str| {
+            daemon
+                .requests()
+                .iter()
+                .any(|r| r.starts_with(&format!("/containers/{}/logs", id)))
+        };
+        assert!(!followed(&ids[1]));
+        assert!(!followed(&ids[3]));
+        let lookup = query_escape(&format!(r#""id":["{}"]"#, ids[2]));
+        assert!(daemon.requests().iter().any(|r| r.contains(&lookup)));
+    }
+
+    #[tokio::test]
+    async fn network_scopes_the_follow() {
+        let (a, b) = ("a".repeat(64), "b".repeat(64));
+        let summary = |id: &str, network: &str| {
+            format!(
+                r#"{{"Id":"{}","NetworkSettings":{{"Networks":{{"{}":{{}}}}}}}}"#,
+                id, network
+            )
+        };
+        let listing = format!("[{},{}]", summary(&a, "bridge"), summary(&b, "mesh"));
+        let mesh = "4f3a9c".repeat(8);
+        let network = mesh.clone();
+        let events: Events = Default::default();
+        let stash = events.clone();
+        let daemon = mock::MockDaemon::start(move |req| match req.uri().path() {
+            "/events" => {
+                let (tx, body) = Body::channel();
+                *stash.lock().unwrap() = Some(tx);
+                Response::new(body)
+            }
+            "/containers/json" => mock::json(200, &listing),
+            path if path.starts_with("/networks/") => {
+                let wanted = path.trim_start_matches("/networks/");
+                if wanted == "mesh" || network.starts_with(wanted) {
+                    mock::json(200, &format!(r#"{{"Name":"mesh","Id":"{}"}}"#, network))
+                } else {
+                    mock::json(404, r#"{"message":"network not found"}"#)
+                }
+            }
+            path if path.ends_with("/json") => mock::json(200, r#"{"Config":{"Tty":false}}"#),
+            path => greeting(path.split('/').nth(2).unwrap(), req.uri().query().unwrap()),
+        });
+
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        for network in ["mesh", &mesh, &mesh[..12]] {
+            let mut lines = system.follow_network(network).await.unwrap();
+            assert_eq!(lines.next().await.unwrap().message, format!("from {}", b));
+        }
+
+        let followed = daemon
+            .requests()
+            .iter()
+            .any(|r| r.starts_with(&format!("/containers/{}/logs", a)));
+        assert!(!followed);
+        assert!(matches!(
+            system.follow_network("nowhere").await,
+            Err(DockerError::Http { status, .. }) if status == StatusCode::NOT_FOUND
+        ));
+    }
+
+    #[tokio::test]
+    async fn services_keep_their_name_across_restarts() {
+        let ids = ["a", "b", "c"].map(|c| c.repeat(64));
+        let summary = |id: &str, labels: &str| {
+            format!(
+                r#"{{"Id":"{}","Names":["/x"],"Labels":{{{}}}}}"#,
+                id, labels
+            )
+        };
+        let web = r#""com.docker.compose.service":"web""#;
+        let listing = Arc::new(Mutex::new(format!(
+            "[{},{}]",
+            summary(&ids[0], web),
+            summary(&ids[1], r#""com.docker.swarm.service.name":"api""#)
+        )));
+        let listed = listing.clone();
+        let events: Events = Default::default();
+        let stash = events.clone();
+        let daemon = mock::MockDaemon::start(move |req| match req.uri().path() {
+            "/events" => {
+                let (tx, body) = Body::channel();
+                *stash.lock().unwrap() = Some(tx);
+                Response::new(body)
+            }
+            "/containers/json" => mock::json(200, &listed.lock().unwrap()),
+            path if path.ends_with("/json") => mock::json(200, r#"{"Config":{"Tty":false}}"#),
+            path => greeting(path.split('/').nth(2).unwrap(), req.uri().query().unwrap()),
+        });
+
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let mut lines = system.follow_services(Default::default()).await.unwrap();
+        let mut first = [lines.next().await.unwrap(), lines.next().await.unwrap()];
+        first.sort_by(|a, b| a.service.cmp(&b.service));
+        assert_eq!(first[0].service, "api");
+        assert_eq!(first[0].line.container_id, ids[1]);
+        assert_eq!(first[1].service, "web");
+
+        // The web container restarts in place, then is scaled up under a new id.
+        send_event(&events, "die", &ids[0], "x").await;
+        send_event(&events, "start", &ids[0], "x").await;
+        let restarted = lines.next().await.unwrap();
+        assert_eq!(
+            (
+                restarted.service.as_str(),
+                restarted.line.container_id.as_str()
+            ),
+            ("web", ids[0].as_str())
+        );
+        let follows = daemon
+            .requests()
+            .into_iter()
+            .filter(|r| r.starts_with(&format!("/containers/{}/logs", ids[0])))
+            .collect::<Vec<_>>();
+        let since = format!("since={}", EVENT_TIME);
+        assert!(follows.last().unwrap().contains(&since), "{:?}", follows);
+
+        *listing.lock().unwrap() = format!("[{}]", summary(&ids[2], web));
+        send_event(&events, "start", &ids[2], "y").await;
+        let scaled = lines.next().await.unwrap();
+        assert_eq!(
+            (scaled.service.as_str(), scaled.line.container_id.as_str()),
+            ("web", ids[2].as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn dropping_the_stream_closes_quiet_follows() {
+        let id = "f".repeat(64);
+        let stdout: Arc<Mutex<Option<Sender>>> = Default::default();
+        let stash = stdout.clone();
+        let (daemon, _events) = daemon(format!(r#"[{{"Id":"{}"}}]"#, id), move |_, query| {
+            let (tx, body) = Body::channel();
+            if query.contains("stdout=1") {
+                *stash.lock().unwrap() = Some(tx);
+            }
+            Response::new(body)
+        });
+
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let lines = system.attach_all().await.unwrap();
+        let mut tx = loop {
+            if let Some(tx) = stdout.lock().unwrap().take() {
+                break tx;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        drop(lines);
+
+        // The container never writes anything, so only the follow itself going
+        // away can close its connection.
+        let closed = tokio::time::timeout(Duration::from_secs(5), async {
+            while futures_lite::future::poll_fn(|cx| tx.poll_ready(cx))
+                .await
+                .is_ok()
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        assert!(closed.is_ok(), "the follow outlived its stream");
+    }
+
+    #[tokio::test]
+    async fn failures_to_attach_are_logged_unless_the_container_is_gone() {
+        let (gone, broken) = ("a".repeat(64), "b".repeat(64));
+        let listing = format!(r#"[{{"Id":"{}"}},{{"Id":"{}"}}]"#, gone, broken);
+        let (daemon, _events) = daemon(listing, |id, _| {
+            if id.starts_with('a') {
+                mock::json(404, r#"{"message":"No such container"}"#)
+            } else {
+                mock::json(500, r#"{"message":"permission denied on the log file"}"#)
+            }
+        });
+
+        let (captured, _guard) = Captured::start();
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let lines = system.attach_all().await.unwrap();
+        let logged = tokio::time::timeout(Duration::from_secs(5), async {
+            while !captured.output().contains("could not follow") {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        drop(lines);
+
+        assert!(logged.is_ok());
+        let output = captured.output();
+        let warnings = output
+            .lines()
+            .filter(|l| l.contains(" WARN "))
+            .collect::<Vec<_>>();
+        match &warnings[..] {
+            [warning] => {
+                assert!(warning.contains(&broken[..12]), "{}", warning);
+                assert!(warning.contains("permission denied on the log file"));
+            }
+            warnings => panic!("expected one warning, got {:?}", warnings),
+        }
+        assert!(!warnings[0].contains(&gone[..12]));
+    }
+
+    #[tokio::test]
+    async fn unreadable_events_are_skipped() {
+        let (a, b) = ("a".repeat(64), "b".repeat(64));
+        let (attaching, events) = daemon("[]".into(), greeting);
+
+        let (captured, _guard) = Captured::start();
+        let system = DockerSystem::unrefreshed(attaching.transport());
+        let mut lines = system.attach_all().await.unwrap();
+        send_line(&events, "{not json").await;
+        send_event(&events, "start", &a, "web").await;
+        assert_eq!(lines.next().await.unwrap().message, format!("from {}", a));
+        assert!(captured
+            .output()
+            .contains("skipping an unreadable daemon event"));
+
+        let listing = format!(r#"[{{"Id":"{}","Names":["/web"]}}]"#, a);
+        let (following, events) = daemon(listing, greeting);
+        let system = DockerSystem::unrefreshed(following.transport());
+        let mut feed = system.follow_named("web").await.unwrap();
+        assert!(matches!(feed.next().await.unwrap(), FollowEvent::Log(_)));
+        send_line(&events, "{not json").await;
+        send_event(&events, "start", &b, "web").await;
+        assert!(matches!(
+            feed.next().await.unwrap(),
+            FollowEvent::Restarted { id, .. } if id == b
+        ));
+    }
+
+    #[tokio::test]
+    async fn paused_containers_stay_attached() {
+        let id = "e".repeat(64);
+        let stdout: Arc<Mutex<Option<Sender>>> = Default::default();
+        let stash = stdout.clone();
+        let (daemon, events) = daemon(format!(r#"[{{"Id":"{}"}}]"#, id), move |_, query| {
+            if query.contains("stdout=1") {
+                let (tx, body) = Body::channel();
+                *stash.lock().unwrap() = Some(tx);
+                Response::new(body)
+            } else {
+                Response::new(Body::empty())
+            }
+        });
+
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let mut feed = system.host_events().await.unwrap();
+        let write = |line: &'static str| {
+            let stdout = stdout.clone();
+            async move {
+                let mut tx = loop {
+                    if let Some(tx) = stdout.lock().unwrap().take() {
+                        break tx;
+                    }
+                    tokio::task::yield_now().await;
+                };
+                tx.send_data(mock::frame(1, line.as_bytes()).into())
+                    .await
+                    .unwrap();
+                *stdout.lock().unwrap() = Some(tx);
+            }
+        };
+        let message = |event| match event {
+            Some(HostEvent::Log(line)) => line.message,
+            e => panic!("unexpected event: {:?}", e),
+        };
+
+        write("before\n").await;
+        assert_eq!(message(feed.next().await), "before");
+
+        send_event(&events, "pause", &id, "web").await;
+        assert_eq!(
+            feed.next().await.unwrap(),
+            HostEvent::Lifecycle(DockerEvent::Paused {
+                id: id.clone(),
+                name: "web".into()
+            })
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        send_event(&events, "unpause", &id, "web").await;
+        assert_eq!(
+            feed.next().await.unwrap(),
+            HostEvent::Lifecycle(DockerEvent::Unpaused {
+                id: id.clone(),
+                name: "web".into()
+            })
+        );
+
+        write("after\n").await;
+        assert_eq!(message(feed.next().await), "after");
+        let follows = daemon.requests();
+        let follows = follows.iter().filter(|r| r.contains("/logs?"));
+        assert_eq!(follows.count(), 2, "reattached after the pause");
+    }
+
+    #[tokio::test]
+    async fn follow_stderr_all_skips_stdout() {
+        let id = "c".repeat(64);
+        let (daemon, _events) = daemon(format!(r#"[{{"Id":"{}"}}]"#, id), |_, query| {
+            if query.contains("stderr=1") {
+                Response::new(mock::frame(2, b"boom\n").into())
+            } else {
+                Response::new(mock::frame(1, b"fine\n").into())
+            }
+        });
+
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let mut feed = system.follow_stderr_all().await.unwrap();
+
+        let line = feed.next().await.unwrap();
+        assert_eq!(line.container_id, id);
+        assert_eq!(line.stream, StreamKind::Stderr);
+        assert_eq!(line.message, "boom");
+        assert!(!daemon
+            .requests()
+            .iter()
+            .any(|r| r.contains("/logs?") && r.contains("stdout=1")));
+    }
+
+    #[tokio::test]
+    async fn compose_project_filters_listing_and_events() {
+        let id = "d".repeat(64);
+        let (daemon, _events) = daemon(format!(r#"[{{"Id":"{}"}}]"#, id), greeting);
+
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let mut feed = system.follow_compose_project("shop").await.unwrap();
+
+        assert_eq!(feed.next().await.unwrap().message, format!("from {}", id));
+
+        let label = query_escape(r#""label":["com.docker.compose.project=shop"]"#);
+        let requests = daemon.requests();
+        assert!(requests
+            .iter()
+            .any(|r| r.starts_with("/events?") && r.contains(&label)));
+        assert!(requests
+            .iter()
+            .any(|r| r.starts_with("/containers/json?") && r.contains(&label)));
+    }
+
+    #[tokio::test]
+    async fn follow_named_reattaches_after_restart() {
+        let (a, b) = ("e".repeat(64), "f".repeat(64));
+        let listing = format!(r#"[{{"Id":"{}","Names":["/web"]}}]"#, a);
+        let (daemon, events) = daemon(listing, greeting);
+
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let mut feed = system.follow_named("web").await.unwrap();
+
+        match feed.next().await.unwrap() {
+            FollowEvent::Log(line) => assert_eq!(line.container_id, a),
+            e => panic!("unexpected event: {:?}", e),
+        }
+
+        send_event(&events, "die", &a, "web").await;
+        send_event(&events, "start", &b, "web").await;
+
+        assert_eq!(
+            feed.next().await.unwrap(),
+            FollowEvent::Restarted {
+                name: "web".into(),
+                id: b.clone()
+            }
+        );
+        match feed.next().await.unwrap() {
+            FollowEvent::Log(line) => assert_eq!(line.message, format!("from {}", b)),
+            e => panic!("unexpected event: {:?}", e),
+        }
+
+        let resumed = format!(
+            "/containers/{}/logs?stdout=1&follow=1&since={}",
+            b, EVENT_TIME
+        );
+        assert!(daemon.requests().iter().any(|r| r == &resumed));
+
+        // Started again without a stop in between, it is followed from the start.
+        let c = "c".repeat(64);
+        send_event(&events, "start", &c, "web").await;
+        assert!(matches!(
+            feed.next().await.unwrap(),
+            FollowEvent::Restarted { id, .. } if id == c
+        ));
+        match feed.next().await.unwrap() {
+            FollowEvent::Log(line) => assert_eq!(line.message, format!("from {}", c)),
+            e => panic!("unexpected event: {:?}", e),
+        }
+        let fresh = format!("/containers/{}/logs?stdout=1&follow=1", c);
+        assert!(
+            daemon.requests().contains(&fresh),
+            "{:?}",
+            daemon.requests()
+        );
+    }
+
+    #[tokio::test]
+    async fn buffered_bytes_are_capped_across_containers() {
+        let (a, b) = ("c".repeat(64), "d".repeat(64));
+        let listing = format!(r#"[{{"Id":"{}"}},{{"Id":"{}"}}]"#, a, b);
+        let (daemon, _events) = daemon(listing, |id, query| {
+            if query.contains("stdout=1") {
+                let lines = format!("{0}-1\n{0}-2\n{0}-3\n", &id[..1]);
+                Response::new(mock::frame(1, lines.as_bytes()).into())
+            } else {
+                Response::new(Body::empty())
+            }
+        });
+
+        let system = DockerSystem::unrefreshed(daemon.transport());
+        let mut lines = system
+            .attach_all_with(AttachOptions {
+                max_buffered_bytes: Some(6),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        // Six lines of three bytes each through a budget of two lines: everything
+        // only arrives because taking lines off the stream makes room again.
+        let mut seen = Vec::new();
+        for _ in 0..6 {
+            let line = tokio::time::timeout(Duration::from_secs(5), lines.next())
+                .await
+                .expect("follows stayed paused")
+                .unwrap();
+            seen.push(line.message);
+        }
+        seen.sort();
+        assert_eq!(seen, vec!["c-1", "c-2", "c-3", "d-1", "d-2", "d-3"]);
+    }
+}
@@ -0,0 +1,167 @@
+//! Just enough RFC 3339 for the timestamps the daemon writes, e.g.
+//! `2024-01-01T12:00:00.123456789Z`.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Parses an RFC 3339 timestamp with an optional fraction of up to nanosecond
+/// precision and either `Z` or a `+hh:mm`/`-hh:mm` offset.
+pub(crate) fn parse(s: &str) -> Option<SystemTime> {
+    let b = s.as_bytes();
+    if b.len() < 20 || b[4] != b'-' || b[7] != b'-' || !matches!(b[10], b'T' | b't' | b' ') {
+        return None;
+    }
+    if b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+
+    let num = |range: std::ops::Range<usize>| digits(s.get(range)?);
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let mut rest = &s[19..];
+    let mut nanos = 0u32;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 {
+            return None;
+        }
+        for (i, digit) in fraction.bytes().take(len.min(9)).enumerate() {
+            nanos += u32::from(digit - b'0') * 10u32.pow(8 - i as u32);
+        }
+        rest = &fraction[len..];
+    }
+
+    let offset = match rest.as_bytes() {
+        [b'Z'] | [b'z'] => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let minutes = digits(&rest[1..3])? * 60 + digits(&rest[4..6])?;
+            if *sign == b'+' {
+                minutes * 60
+            } else {
+                -minutes * 60
+            }
+        }
+        _ => return None,
+    };
+
+    let secs =
+        days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second - offset;
+    let time = if secs >= 0 {
+        UNIX_EPOCH + Duration::new(secs as u64, nanos)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()) + Duration::from_nanos(nanos.into())
+    };
+
+    Some(time)
+}
+
+/// Formats `time` the way the daemon does, in UTC with nanoseconds.
+pub(crate) fn format(time: SystemTime) -> String {
+    let (secs, nanos) = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => (since.as_secs() as i64, since.subsec_nanos()),
+        Err(e) => {
+            let before = e.duration();
+            let secs = -(before.as_secs() as i64);
+            match before.subsec_nanos() {
+                0 => (secs, 0),
+                nanos => (secs - 1, 1_000_000_000 - nanos),
+            }
+        }
+    };
+
+    let (days, second_of_day) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        second_of_day / 3_600,
+        second_of_day / 60 % 60,
+        second_of_day % 60,
+        nanos
+    )
+}
+
+fn digits(s: &str) -> Option<i64> {
+    if s.bytes().all(|d| d.is_ascii_digit()) {
+        s.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The proleptic Gregorian date `days` after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{format, parse};
+
+    #[test]
+    fn parses_daemon_timestamps() {
+        let at = |secs, nanos| Some(UNIX_EPOCH + Duration::new(secs, nanos));
+
+        assert_eq!(parse("1970-01-01T00:00:00Z"), at(0, 0));
+        assert_eq!(parse("2024-01-01T00:00:00Z"), at(1_704_067_200, 0));
+        assert_eq!(
+            parse("2024-02-29T12:30:15.123456789Z"),
+            at(1_709_209_815, 123_456_789)
+        );
+        assert_eq!(
+            parse("2024-02-29T12:30:15.5Z"),
+            at(1_709_209_815, 500_000_000)
+        );
+        assert_eq!(parse("2024-01-01T02:00:00+02:00"), at(1_704_067_200, 0));
+        assert_eq!(parse("2023-12-31T19:00:00-05:00"), at(1_704_067_200, 0));
+
+        assert_eq!(parse("hello world"), None);
+        assert_eq!(parse("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse("2024-01-01T00:00:00"), None);
+        assert_eq!(parse("2024-01-01T00:00:00.Z"), None);
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        for s in [
+            "1970-01-01T00:00:00.000000000Z",
+            "2024-02-29T12:30:15.123456789Z",
+            "1969-12-31T23:59:59.500000000Z",
+            "2100-03-01T00:00:00.000000001Z",
+        ] {
+            assert_eq!(format(parse(s).unwrap()), s);
+        }
+    }
+}
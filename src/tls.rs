@@ -0,0 +1,292 @@
+//! The TLS side of talking to a daemon over TCP, set up the way `docker --tlsverify`
+//! does from a CA and a client certificate.
+
+use std::{
+    convert::TryFrom,
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio_rustls::rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{self, CryptoProvider},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+
+use crate::DockerError;
+
+/// The certificates a TLS connection to the daemon is made with, as PEM files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// The CA the daemon's certificate must be signed by. Without one the usual
+    /// public roots are trusted.
+    pub ca: Option<PathBuf>,
+    /// The client certificate for a daemon that asks for one, with `key` its
+    /// private key. Both or neither must be set.
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// Accept whatever certificate the daemon presents, like a context's
+    /// `SkipTLSVerify`. The connection is still encrypted, but anyone in the
+    /// middle can read it.
+    pub skip_verify: bool,
+}
+
+impl TlsConfig {
+    pub(crate) fn client_config(&self) -> Result<Arc<ClientConfig>, DockerError> {
+        let provider = Arc::new(crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| DockerError::InvalidConfig(format!("tls: {}", e)))?;
+
+        let builder = if self.skip_verify {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(Unverified(provider)))
+        } else {
+            let mut roots = RootCertStore::empty();
+            match &self.ca {
+                Some(ca) => {
+                    for cert in certs(ca)? {
+                        roots.add(cert).map_err(|e| invalid_pem(ca, e))?;
+                    }
+                }
+                None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+            }
+            builder.with_root_certificates(roots)
+        };
+
+        let config = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(certs(cert)?, private_key(key)?)
+                .map_err(|e| invalid_pem(cert, e))?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(DockerError::InvalidConfig(
+                    "a tls client certificate needs its key, and the key its certificate".into(),
+                ))
+            }
+        };
+
+        Ok(Arc::new(config))
+    }
+}
+
+/// What the daemon's certificate is checked for: `host`, as the connection was
+/// made to it.
+pub(crate) fn server_name(host: &str) -> Result<ServerName<'static>, DockerError> {
+    ServerName::try_from(host.to_owned())
+        .map_err(|_| DockerError::InvalidConfig(format!("{:?} is not a tls server name", host)))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, DockerError> {
+    fs::read(path)
+        .map_err(|e| DockerError::InvalidConfig(format!("can't read {}: {}", path.display(), e)))
+}
+
+fn invalid_pem(path: &Path, e: impl std::fmt::Display) -> DockerError {
+    DockerError::InvalidConfig(format!("{}: {}", path.display(), e))
+}
+
+fn certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, DockerError> {
+    let pem = read(path)?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(&pem[..]))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid_pem(path, e))?;
+
+    if certs.is_empty() {
+        return Err(invalid_pem(path, "no certificates in it"));
+    }
+    Ok(certs)
+}
+
+fn private_key(path: &Path) -> Result<PrivateKeyDer<'static>, DockerError> {
+    let pem = read(path)?;
+    rustls_pemfile::private_key(&mut BufReader::new(&pem[..]))
+        .map_err(|e| invalid_pem(path, e))?
+        .ok_or_else(|| invalid_pem(path, "no private key in it"))
+}
+
+/// Takes any certificate, for [`TlsConfig::skip_verify`]. Signatures are still
+/// checked, so the handshake itself stays sound.
+#[derive(Debug)]
+struct Unverified(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for Unverified {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, fs, sync::Arc};
+
+    use hyper::{server::conn::Http, service::service_fn, Body, Response};
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use tokio::net::TcpListener;
+    use tokio_rustls::{
+        rustls::{
+            crypto::ring::default_provider,
+            pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+            server::WebPkiClientVerifier,
+            RootCertStore, ServerConfig,
+        },
+        TlsAcceptor,
+    };
+
+    use super::TlsConfig;
+    use crate::{transport::Transport, DockerConfig, DockerError, TcpEndpoint};
+
+    #[tokio::test]
+    async fn requests_go_over_tls_with_the_context_certificates() {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = params.clone().self_signed(&ca_key).unwrap();
+        let signed = |name: &str| {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(vec![name.to_owned()])
+                .unwrap()
+                .signed_by(&key, &ca, &ca_key)
+                .unwrap();
+            (cert, key)
+        };
+        let (server_cert, server_key) = signed("localhost");
+        let (client_cert, client_key) = signed("client");
+
+        // The daemon only takes clients with a certificate from the same CA.
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let provider = Arc::new(default_provider());
+        let verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider.clone())
+            .build()
+            .unwrap();
+        let server = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(
+                vec![CertificateDer::from(server_cert.der().to_vec())],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(server_key.serialize_der())),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    if let Ok(stream) = acceptor.accept(stream).await {
+                        let ping = service_fn(|_| async {
+                            Ok::<_, Infallible>(Response::new(Body::from("OK")))
+                        });
+                        let _ = Http::new().serve_connection(stream, ping).await;
+                    }
+                });
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let pem = |file: &str, contents: String| {
+            let path = dir.path().join(file);
+            fs::write(&path, contents).unwrap();
+            Some(path)
+        };
+        let tls = TlsConfig {
+            ca: pem("ca.pem", ca.pem()),
+            cert: pem("cert.pem", client_cert.pem()),
+            key: pem("key.pem", client_key.serialize_pem()),
+            skip_verify: false,
+        };
+        let ping = |tls: TlsConfig| async move {
+            let config = DockerConfig {
+                tcp: Some(TcpEndpoint {
+                    host: "localhost".into(),
+                    port,
+                    tls: Some(tls),
+                }),
+                ..Default::default()
+            };
+            let transport = Transport::configured("", &config).unwrap();
+            let response = transport.get_ok("/_ping").await?;
+            crate::transport::read_body(response).await
+        };
+
+        assert_eq!(ping(tls.clone()).await.unwrap(), b"OK");
+        // Without the client certificate the daemon hangs up on us.
+        let anonymous = TlsConfig {
+            cert: None,
+            key: None,
+            ..tls.clone()
+        };
+        assert!(ping(anonymous).await.is_err());
+        // And a daemon signed by some other CA isn't trusted.
+        let other_key = KeyPair::generate().unwrap();
+        let other = params.self_signed(&other_key).unwrap();
+        let stranger = pem("other.pem", other.pem());
+        assert!(ping(TlsConfig {
+            ca: stranger,
+            ..tls.clone()
+        })
+        .await
+        .is_err());
+        assert!(matches!(
+            Transport::configured(
+                "",
+                &DockerConfig {
+                    tcp: Some(TcpEndpoint {
+                        host: "localhost".into(),
+                        port,
+                        tls: Some(TlsConfig { key: None, ..tls }),
+                    }),
+                    ..Default::default()
+                }
+            ),
+            Err(DockerError::InvalidConfig(_))
+        ));
+    }
+}
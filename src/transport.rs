@@ -0,0 +1,531 @@
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicU64, Arc},
+    time::Duration,
+};
+
+use flate2::write::GzDecoder;
+use hyper::{
+    body::{Bytes, HttpBody},
+    header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, USER_AGENT},
+    Body, Client, Request, Response,
+};
+use once_cell::sync::Lazy;
+
+use crate::{connector::Connector, DockerError, TlsConfig};
+
+/// Where the daemon listens unless configured otherwise.
+pub const DEFAULT_DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+/// The client [`Transport::default`] shares, so the convenience constructors that
+/// always talk to the default socket don't each set up a pool of their own.
+static UNIX_CLIENT: Lazy<Client<Connector>> = Lazy::new(|| pooled_client(Connector::default()));
+
+/// Follow requests hold their connection for as long as the container runs, but
+/// everything else (listing, inspect, snapshots) is short-lived, so finished
+/// connections are kept around for the next request instead of dialing the socket
+/// again each time.
+fn pooled_client(connector: Connector) -> Client<Connector> {
+    Client::builder()
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(32)
+        .build(connector)
+}
+
+/// Settings applied to every request a [`DockerSystem`](crate::DockerSystem) makes.
+#[derive(Debug, Clone)]
+pub struct DockerConfig {
+    /// The daemon's unix socket. Defaults to [`DEFAULT_DOCKER_SOCKET`].
+    pub socket: PathBuf,
+    /// Sent as the `User-Agent` header, so requests can be told apart in the
+    /// daemon's logs. Defaults to `docker-log-stream/{version}`.
+    pub user_agent: String,
+    /// Check the daemon answers `/_ping` before listing anything when connecting,
+    /// so a daemon that is down fails fast with
+    /// [`DockerError::DaemonUnreachable`]. On by default.
+    pub ping_on_connect: bool,
+    /// How many of its latest non-empty refreshes a
+    /// [`DockerSystem`](crate::DockerSystem) keeps for
+    /// [`recent_deltas`](crate::DockerSystem::recent_deltas). Defaults to 8; 0 keeps
+    /// none.
+    pub retained_deltas: usize,
+    /// Asks for a socket receive buffer (`SO_RCVBUF`) of this many bytes on every
+    /// connection to the daemon, which helps keep up with very chatty containers.
+    /// The kernel may round it or cap it (at `net.core.rmem_max` on Linux); if it
+    /// refuses the option outright the connection keeps its default buffer and a
+    /// debug event is traced. By default the system's default is left alone.
+    pub recv_buffer_bytes: Option<usize>,
+    /// Talk to a daemon listening on TCP instead of on `socket`, as a
+    /// `tcp://` context does. Unset by default.
+    pub tcp: Option<TcpEndpoint>,
+}
+
+/// A daemon's TCP address, like `-H tcp://host:port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpEndpoint {
+    pub host: String,
+    pub port: u16,
+    /// Connect with TLS, as `docker --tlsverify` does. Without it requests go out
+    /// in plain HTTP.
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for DockerConfig {
+    fn default() -> Self {
+        Self {
+            socket: PathBuf::from(DEFAULT_DOCKER_SOCKET),
+            user_agent: concat!("docker-log-stream/", env!("CARGO_PKG_VERSION")).to_owned(),
+            ping_on_connect: true,
+            retained_deltas: 8,
+            recv_buffer_bytes: None,
+            tcp: None,
+        }
+    }
+}
+
+/// Where and how requests to the daemon are sent.
+#[derive(Debug, Clone)]
+pub(crate) struct Transport {
+    client: Client<Connector>,
+    socket: PathBuf,
+    tcp: Option<Arc<TcpEndpoint>>,
+    user_agent: HeaderValue,
+    /// Follows reopened after their connection dropped, shared by every follow
+    /// made through this transport.
+    reconnects: Arc<AtomicU64>,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        let config = DockerConfig::default();
+        Self {
+            client: UNIX_CLIENT.clone(),
+            socket: config.socket,
+            tcp: None,
+            user_agent: HeaderValue::from_static(concat!(
+                "docker-log-stream/",
+                env!("CARGO_PKG_VERSION")
+            )),
+            reconnects: Default::default(),
+        }
+    }
+}
+
+impl Transport {
+    #[cfg(test)]
+    pub(crate) fn new(socket: impl Into<PathBuf>) -> Self {
+        Self::configured(socket, &DockerConfig::default()).expect("default config is valid")
+    }
+
+    pub(crate) fn configured(
+        socket: impl Into<PathBuf>,
+        config: &DockerConfig,
+    ) -> Result<Self, DockerError> {
+        let user_agent = HeaderValue::from_str(&config.user_agent).map_err(|_| {
+            DockerError::InvalidConfig(format!(
+                "user agent {:?} is not a valid header value",
+                config.user_agent
+            ))
+        })?;
+
+        let tls = match config.tcp.as_ref().and_then(|tcp| tcp.tls.as_ref()) {
+            Some(tls) => Some(tls.client_config()?),
+            None => None,
+        };
+        // The address errors are reported against, in place of a socket path.
+        let socket = match &config.tcp {
+            Some(tcp) => PathBuf::from(format!("tcp://{}", authority(tcp))),
+            None => socket.into(),
+        };
+
+        Ok(Self {
+            client: pooled_client(Connector {
+                recv_buffer_bytes: config.recv_buffer_bytes,
+                tls,
+            }),
+            socket,
+            tcp: config.tcp.clone().map(Arc::new),
+            user_agent,
+            reconnects: Default::default(),
+        })
+    }
+
+    /// The daemon's socket, or `tcp://host:port` for one on TCP.
+    pub(crate) fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    pub(crate) fn reconnects(&self) -> &Arc<AtomicU64> {
+        &self.reconnects
+    }
+
+    pub(crate) fn uri(&self, path_and_query: &str) -> hyper::Uri {
+        match &self.tcp {
+            Some(tcp) => format!("http://{}{}", authority(tcp), path_and_query)
+                .parse()
+                .expect("endpoint and path are a valid uri"),
+            None => hyperlocal::Uri::new(&self.socket, path_and_query).into(),
+        }
+    }
+
+    /// A curl command making the same request, for reproducing it by hand.
+    pub(crate) fn curl(&self, path_and_query: &str) -> String {
+        let tcp = match &self.tcp {
+            Some(tcp) => tcp,
+            None => {
+                return format!(
+                    "curl --unix-socket {} 'http://localhost{}'",
+                    self.socket.display(),
+                    path_and_query
+                )
+            }
+        };
+        let tls = match &tcp.tls {
+            Some(tls) => tls,
+            None => return format!("curl 'http://{}{}'", authority(tcp), path_and_query),
+        };
+
+        let mut curl = String::from("curl");
+        if tls.skip_verify {
+            curl.push_str(" --insecure");
+        }
+        for (flag, path) in [("cacert", &tls.ca), ("cert", &tls.cert), ("key", &tls.key)] {
+            if let Some(path) = path {
+                curl.push_str(&format!(" --{} {}", flag, path.display()));
+            }
+        }
+        format!("{} 'https://{}{}'", curl, authority(tcp), path_and_query)
+    }
+
+    pub(crate) async fn get(&self, path_and_query: &str) -> Result<Response<Body>, DockerError> {
+        let request = Request::get(self.uri(path_and_query))
+            .header(USER_AGENT, self.user_agent.clone())
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .expect("request parts are valid");
+
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|e| request_error(&self.socket, e))?;
+        decoded(response)
+    }
+
+    /// Issues a GET and fails with [`DockerError::Http`] unless the daemon answered 2xx.
+    pub(crate) async fn get_ok(&self, path_and_query: &str) -> Result<Response<Body>, DockerError> {
+        let response = self.get(path_and_query).await?;
+
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(http_error(response).await)
+        }
+    }
+
+    pub(crate) async fn get_json(
+        &self,
+        path_and_query: &str,
+    ) -> Result<serde_json::Value, DockerError> {
+        let response = self.get_ok(path_and_query).await?;
+        let buf = read_body(response).await?;
+
+        Ok(serde_json::from_slice(&buf)?)
+    }
+}
+
+/// `host:port`, with an IPv6 host in brackets.
+fn authority(tcp: &TcpEndpoint) -> String {
+    if tcp.host.contains(':') && !tcp.host.starts_with('[') {
+        format!("[{}]:{}", tcp.host, tcp.port)
+    } else {
+        format!("{}:{}", tcp.host, tcp.port)
+    }
+}
+
+/// Tells a socket this user may not open apart from the daemon not answering, as
+/// it is by far the most common reason the first request fails.
+fn request_error(socket: &Path, e: hyper::Error) -> DockerError {
+    if e.is_connect() {
+        let mut source = e.source();
+        while let Some(cause) = source {
+            if let Some(io) = cause.downcast_ref::<io::Error>() {
+                if io.kind() == io::ErrorKind::PermissionDenied {
+                    return DockerError::PermissionDenied {
+                        path: socket.to_owned(),
+                    };
+                }
+            }
+            source = cause.source();
+        }
+    }
+
+    DockerError::Transport(e)
+}
+
+/// Undoes the `Content-Encoding` of a body, so whatever reads it sees what was
+/// sent. The daemon itself never compresses (logs of a driver that keeps its
+/// rotated files gzipped come back inflated), but a proxy in front of it may.
+/// Only gzip is understood, and it is all [`Transport::get`] asks for.
+pub(crate) fn decoded(response: Response<Body>) -> Result<Response<Body>, DockerError> {
+    let encoding = match response.headers().get(CONTENT_ENCODING) {
+        Some(encoding) => encoding
+            .to_str()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase(),
+        None => return Ok(response),
+    };
+
+    match encoding.as_str() {
+        "" | "identity" => Ok(response),
+        "gzip" | "x-gzip" => {
+            let (mut parts, body) = response.into_parts();
+            parts.headers.remove(CONTENT_ENCODING);
+            parts.headers.remove(CONTENT_LENGTH);
+            Ok(Response::from_parts(parts, gunzip(body)))
+        }
+        other => Err(DockerError::InvalidResponse(format!(
+            "unsupported content encoding {:?}",
+            other
+        ))),
+    }
+}
+
+/// Inflates a gzip body a chunk at a time as it arrives, so a follow stays live.
+fn gunzip(body: Body) -> Body {
+    let start = Some((body, GzDecoder::new(Vec::new())));
+    let inflated = futures_lite::stream::unfold(start, |state| async move {
+        let (mut body, mut decoder) = state?;
+
+        loop {
+            let more = match body.data().await {
+                Some(Ok(chunk)) => decoder
+                    .write_all(&chunk)
+                    .and_then(|()| decoder.flush())
+                    .map(|()| true),
+                Some(Err(e)) => Err(io::Error::other(e)),
+                // Also fails if the body was cut short of the gzip trailer.
+                None => decoder.try_finish().map(|()| false),
+            };
+            let more = match more {
+                Ok(more) => more,
+                Err(e) => return Some((Err(e), None)),
+            };
+
+            let out = std::mem::take(decoder.get_mut());
+            if !out.is_empty() {
+                let next = if more { Some((body, decoder)) } else { None };
+                return Some((Ok(Bytes::from(out)), next));
+            }
+            if !more {
+                return None;
+            }
+        }
+    });
+
+    Body::wrap_stream(inflated)
+}
+
+pub(crate) async fn read_body(mut response: Response<Body>) -> Result<Vec<u8>, DockerError> {
+    let mut buf: Vec<u8> = Vec::with_capacity(
+        (response
+            .size_hint()
+            .upper()
+            .unwrap_or_else(|| response.size_hint().lower())) as usize,
+    );
+
+    while let Some(data) = response.data().await {
+        let data = data?;
+        buf.extend_from_slice(&data);
+    }
+
+    Ok(buf)
+}
+
+/// Turns a non-success response into [`DockerError::Http`], using the daemon's
+/// `{"message": ...}` body when there is one.
+pub(crate) async fn http_error(response: Response<Body>) -> DockerError {
+    let status = response.status();
+    let message = match read_body(response).await {
+        Ok(buf) => serde_json::from_slice::<serde_json::Value>(&buf)
+            .ok()
+            .and_then(|v| v.get("message")?.as_str().map(str::to_owned))
+            .unwrap_or_else(|| String::from_utf8_lossy(&buf).trim().to_owned()),
+        Err(e) => e.to_string(),
+    };
+
+    DockerError::Http { status, message }
+}
+
+/// `?filters=...` for a json `filters` object, or nothing when it is empty.
+pub(crate) fn filters_query(filters: &serde_json::Value) -> String {
+    match filters.as_object() {
+        Some(object) if !object.is_empty() => {
+            format!("?filters={}", query_escape(&filters.to_string()))
+        }
+        _ => String::new(),
+    }
+}
+
+/// Percent-encodes a value for use in a query string.
+pub(crate) fn query_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        future::{ready, Ready},
+        io,
+        os::unix::fs::PermissionsExt,
+        path::Path,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    };
+
+    use hyper::{
+        header::{ACCEPT_ENCODING, USER_AGENT},
+        service::Service,
+        Client, Uri,
+    };
+
+    use super::{request_error, DockerConfig, Transport};
+    use crate::{connector::DaemonStream, mock, DockerError};
+
+    #[tokio::test]
+    async fn user_agent_is_sent_and_configurable() {
+        let seen: Arc<Mutex<Vec<String>>> = Default::default();
+        let log = seen.clone();
+        let daemon = mock::MockDaemon::start(move |req| {
+            let agent = req.headers()[USER_AGENT].to_str().unwrap().to_owned();
+            log.lock().unwrap().push(agent);
+            mock::json(200, "[]")
+        });
+
+        daemon
+            .transport()
+            .get_json("/containers/json")
+            .await
+            .unwrap();
+
+        let config = DockerConfig {
+            user_agent: "my-dashboard/2".into(),
+            ..Default::default()
+        };
+        Transport::configured(&daemon.socket, &config)
+            .unwrap()
+            .get_json("/containers/json")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                format!("docker-log-stream/{}", env!("CARGO_PKG_VERSION")),
+                "my-dashboard/2".to_owned()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn gzip_is_asked_for() {
+        let seen: Arc<Mutex<Vec<String>>> = Default::default();
+        let log = seen.clone();
+        let daemon = mock::MockDaemon::start(move |req| {
+            let accepted = req.headers().get(ACCEPT_ENCODING);
+            let accepted = accepted.map(|v| v.to_str().unwrap().to_owned());
+            log.lock().unwrap().extend(accepted);
+            mock::json(200, "[]")
+        });
+
+        daemon
+            .transport()
+            .get_json("/containers/json")
+            .await
+            .unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), vec!["gzip".to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn short_requests_reuse_pooled_connections() {
+        let daemon = mock::MockDaemon::start(|_| mock::json(200, "[]"));
+        let transport = daemon.transport();
+
+        for _ in 0..50 {
+            transport.get_json("/containers/json").await.unwrap();
+        }
+
+        assert_eq!(daemon.requests().len(), 50);
+        assert_eq!(daemon.connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn unreadable_socket_is_permission_denied() {
+        let daemon = mock::MockDaemon::start(|_| mock::json(200, "[]"));
+        fs::set_permissions(&daemon.socket, fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Root opens the socket regardless of its mode, so only check the real
+        // thing as someone else; the refusal itself is simulated below either way.
+        if !nix::unistd::geteuid().is_root() {
+            match daemon.transport().get("/_ping").await {
+                Err(DockerError::PermissionDenied { path }) => assert_eq!(path, daemon.socket),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        #[derive(Clone)]
+        struct Refused(io::ErrorKind);
+        impl Service<Uri> for Refused {
+            type Response = DaemonStream;
+            type Error = io::Error;
+            type Future = Ready<io::Result<DaemonStream>>;
+
+            fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, _: Uri) -> Self::Future {
+                ready(Err(self.0.into()))
+            }
+        }
+
+        let socket = Path::new("/var/run/docker.sock");
+        let refused = |kind| async move {
+            let client = Client::builder().build::<_, hyper::Body>(Refused(kind));
+            let e = client
+                .get(Uri::from_static("http://localhost/"))
+                .await
+                .unwrap_err();
+            request_error(socket, e)
+        };
+
+        match refused(io::ErrorKind::PermissionDenied).await {
+            DockerError::PermissionDenied { path } => assert_eq!(path, socket),
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(refused(io::ErrorKind::PermissionDenied)
+            .await
+            .to_string()
+            .contains("`docker` group"));
+        assert!(matches!(
+            refused(io::ErrorKind::NotFound).await,
+            DockerError::Transport(_)
+        ));
+    }
+}